@@ -0,0 +1,19 @@
+use firma2_lib::{addresses, clap::Parser, read_stdin_seed, serde_json};
+
+fn main() {
+    let params = addresses::Params::parse();
+    let seed = match params.descriptor {
+        Some(_) => None,
+        None => match read_stdin_seed() {
+            Ok(s) => Some(s),
+            Err(e) => panic!("{e:?}"),
+        },
+    };
+    match addresses::main(seed.as_ref(), params) {
+        Ok(o) => {
+            let j = serde_json::to_string_pretty(&o).expect("doesn't contain non-string key");
+            println!("{j}",)
+        }
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
@@ -0,0 +1,12 @@
+use firma2_lib::{check_presigned, clap::Parser, serde_json};
+
+fn main() {
+    let params = check_presigned::Params::parse();
+    match check_presigned::main(params) {
+        Ok(o) => {
+            let j = serde_json::to_string_pretty(&o).expect("doesn't contain non-string key");
+            println!("{j}",)
+        }
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
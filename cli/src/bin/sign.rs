@@ -2,11 +2,17 @@ use firma2_lib::{clap::Parser, read_stdin_seed, serde_json, sign};
 
 fn main() {
     let params = sign::Params::parse();
+    let text = params.text;
     let seed = match read_stdin_seed() {
         Ok(s) => s,
         Err(e) => panic!("{e:?}"),
     };
     match sign::main(&seed, params) {
+        Ok(o) if text => {
+            for output in o {
+                println!("{output}\n")
+            }
+        }
         Ok(o) => {
             let j = serde_json::to_string_pretty(&o).expect("doesn't contain non-string key");
             println!("{j}",)
@@ -0,0 +1,12 @@
+use firma2_lib::{clap::Parser, serde_json, verify_log};
+
+fn main() {
+    let params = verify_log::Params::parse();
+    match verify_log::main(params) {
+        Ok(o) => {
+            let j = serde_json::to_string_pretty(&o).expect("doesn't contain non-string key");
+            println!("{j}",)
+        }
+        Err(e) => eprintln!("{e:?}"),
+    }
+}
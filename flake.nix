@@ -59,5 +59,40 @@
           program = "${defaultPackage}/bin/spendable";
         };
 
+        apps.addresses = {
+          type = "app";
+          program = "${defaultPackage}/bin/addresses";
+        };
+
+        apps.split = {
+          type = "app";
+          program = "${defaultPackage}/bin/split";
+        };
+
+        apps.create = {
+          type = "app";
+          program = "${defaultPackage}/bin/create";
+        };
+
+        apps.import = {
+          type = "app";
+          program = "${defaultPackage}/bin/import";
+        };
+
+        apps.inheritance = {
+          type = "app";
+          program = "${defaultPackage}/bin/inheritance";
+        };
+
+        apps.check-presigned = {
+          type = "app";
+          program = "${defaultPackage}/bin/check-presigned";
+        };
+
+        apps.verify-log = {
+          type = "app";
+          program = "${defaultPackage}/bin/verify-log";
+        };
+
       });
 }
@@ -0,0 +1,56 @@
+//! Compares the time to sign the PSBT in `wallet/psbt_file` when ownership comes from the key
+//! origins in the PSBT with the time needed when the key origins are missing and the first `max`
+//! addresses of the standard descriptors are generated, as every run did before.
+//!
+//! The PSBT is a payment with a change output carrying key origins.
+//!
+//! Run with `cargo bench -p firma2-lib`
+
+use firma2_lib::bitcoin::Psbt;
+use firma2_lib::clap::Parser;
+use firma2_lib::{sign, Seed};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+
+const MNEMONIC: &str = include_str!("../../wallet/MNEMONIC");
+const PSBT: &str = include_str!("../../wallet/psbt_file");
+const RUNS: u32 = 10;
+
+fn bench(seed: &Seed, psbt: &Psbt, max: u32) -> Duration {
+    let mut f = NamedTempFile::new().expect("bench");
+    f.as_file_mut()
+        .write_all(psbt.to_string().as_bytes())
+        .expect("bench");
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let path = f.path().to_str().expect("utf8 path");
+        let params = sign::Params::parse_from([
+            "sign",
+            "--network",
+            "testnet",
+            "--max",
+            &max.to_string(),
+            path,
+        ]);
+        let output = sign::main(seed, params).expect("bench").remove(0);
+        assert_eq!(output.inputs[0].ownership, sign::Ownership::Ours);
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let seed: Seed = MNEMONIC.parse().expect("bench");
+    let psbt: Psbt = PSBT.trim().parse().expect("bench");
+    let mut stripped = psbt.clone();
+    for input in stripped.inputs.iter_mut() {
+        input.tap_key_origins.clear();
+        input.bip32_derivation.clear();
+    }
+
+    for max in [100, 1000] {
+        let origins = bench(&seed, &psbt, max);
+        let scan = bench(&seed, &stripped, max);
+        println!("max {max:>4}: key origins {origins:>12?}, address scan {scan:>12?}");
+    }
+}
@@ -0,0 +1,205 @@
+use crate::import::{account_parser, compute_descriptors};
+use crate::spendable::derive_addresses;
+use crate::{error::Error, seed::Seed};
+use bitcoin::{key::Secp256k1, Network};
+use clap::Parser;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+
+/// Returns the addresses of the given descriptor, or of the standard descriptors (bip 44, 49, 84, 86)
+/// of the seed taken from standard input if the descriptor is not given
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// Descriptor to derive the addresses from, multipath descriptors are splitted in the
+    /// external and internal ones
+    #[clap(short, long)]
+    pub descriptor: Option<Descriptor<DescriptorPublicKey>>,
+
+    /// Derivation index of the first address
+    #[clap(short, long, default_value_t = 0)]
+    pub start: u32,
+
+    /// Number of addresses for every single descriptor
+    #[clap(long, default_value_t = 10)]
+    pub number: u32,
+
+    /// Bitcoin Network. bitcoin,testnet,signet are possible values
+    #[clap(short, long, env)]
+    pub network: Network,
+
+    /// Account number used in the derivation path of the standard descriptors, like in `84'/0'/account'`
+    #[clap(short, long, default_value_t = 0, value_parser = account_parser())]
+    pub account: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// Single descriptor the addresses are derived from
+    pub desc: String,
+
+    /// Known only for the single descriptors of a descriptor with two paths like `<0;1>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keychain: Option<Keychain>,
+
+    pub addresses: Vec<IndexedAddress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Keychain {
+    /// Receiving addresses
+    External,
+
+    /// Change addresses
+    Internal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedAddress {
+    pub address: String,
+
+    pub index: u32,
+}
+
+pub fn main(seed: Option<&Seed>, params: Params) -> Result<Vec<Output>, Error> {
+    let Params {
+        descriptor,
+        start,
+        number,
+        network,
+        account,
+    } = params;
+
+    let descriptors = match (descriptor, seed) {
+        (Some(descriptor), _) => vec![descriptor],
+        (None, Some(seed)) => compute_descriptors(seed, network, &Secp256k1::new(), account),
+        (None, None) => return Err(Error::Other("either descriptor or seed are needed")),
+    };
+
+    let mut result = vec![];
+    for desc in descriptors {
+        let singles = desc.into_single_descriptors()?;
+        let two_paths = singles.len() == 2;
+        for (i, single) in singles.into_iter().enumerate() {
+            let keychain = match (two_paths, i) {
+                (true, 0) => Some(Keychain::External),
+                (true, _) => Some(Keychain::Internal),
+                (false, _) => None,
+            };
+            let addresses =
+                derive_addresses(&single, start..start.saturating_add(number), network)?
+                    .into_iter()
+                    .map(|(index, address)| IndexedAddress {
+                        address: address.to_string(),
+                        index,
+                    })
+                    .collect();
+            result.push(Output {
+                desc: single.to_string(),
+                keychain,
+                addresses,
+            });
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::Network;
+
+    use crate::Seed;
+
+    use super::{Keychain, Params};
+
+    const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
+    const DESCRIPTOR_TESTNET: &str = include_str!("../../wallet/descriptor_testnet");
+    const DESCRIPTOR_TESTNET_EXTERNAL: &str =
+        include_str!("../../wallet/descriptor_testnet_external");
+    const FIRST_ADDRESS_TESTNET: &str = include_str!("../../wallet/first_address_testnet");
+
+    #[test]
+    fn test_addresses_descriptor() {
+        let params = Params {
+            descriptor: Some(DESCRIPTOR_TESTNET.parse().expect("test")),
+            start: 0,
+            number: 2,
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(None, params).expect("test");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].desc, DESCRIPTOR_TESTNET_EXTERNAL);
+        assert_eq!(result[0].keychain, Some(Keychain::External));
+        assert_eq!(result[0].addresses[0].address, FIRST_ADDRESS_TESTNET);
+        assert_eq!(
+            result[0].addresses[1].address,
+            "tb1ps4e34gzelyrt0uvujgz7p5tdjzt7qz8kgnnt4zvle3u8twvhhcfqs7nu9e"
+        );
+        assert_eq!(result[1].keychain, Some(Keychain::Internal));
+        assert_eq!(
+            result[1].addresses[0].address,
+            "tb1p7mudcfml9qa6fmpcx6r5wwwnwlgnev953kq8t6z0sqvndpusqr5st5tmdy"
+        );
+
+        let params = Params {
+            descriptor: Some(DESCRIPTOR_TESTNET_EXTERNAL.parse().expect("test")),
+            start: 1,
+            number: 3,
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(None, params).expect("test");
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].keychain, None);
+        assert_eq!(result[0].addresses.len(), 3);
+        assert_eq!(result[0].addresses[0].index, 1);
+        assert_eq!(
+            result[0].addresses[0].address,
+            "tb1ps4e34gzelyrt0uvujgz7p5tdjzt7qz8kgnnt4zvle3u8twvhhcfqs7nu9e"
+        );
+
+        // with more than two paths the keychains are unknown
+        let three_paths = DESCRIPTOR_TESTNET
+            .split('#')
+            .next()
+            .expect("test")
+            .replace("<0;1>", "<0;1;2>");
+        let params = Params {
+            descriptor: Some(three_paths.parse().expect("test")),
+            start: 0,
+            number: 1,
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(None, params).expect("test");
+        assert_eq!(result.len(), 3);
+        assert!(result.iter().all(|r| r.keychain.is_none()));
+    }
+
+    #[test]
+    fn test_addresses_seed() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = Params {
+            descriptor: None,
+            start: 0,
+            number: 1,
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(Some(&seed), params).expect("test");
+        assert_eq!(result.len(), 8);
+        assert_eq!(result[6].desc, DESCRIPTOR_TESTNET_EXTERNAL);
+        assert_eq!(result[6].addresses[0].address, FIRST_ADDRESS_TESTNET);
+
+        let params = Params {
+            descriptor: None,
+            start: 0,
+            number: 1,
+            network: Network::Testnet,
+            account: 0,
+        };
+        assert!(super::main(None, params).is_err());
+    }
+}
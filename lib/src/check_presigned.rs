@@ -0,0 +1,211 @@
+use crate::inheritance::LockType;
+use crate::Error;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::{absolute, OutPoint, Transaction, Txid};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Check pre-signed transactions, like the ones of an inheritance bundle, against a snapshot of
+/// the unspent outputs, reporting the ones with inputs already spent which must be signed again
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// Json file with the unspent outputs, objects with `txid` and `vout` like the ones returned
+    /// by `bitcoin-cli listunspent`
+    #[clap(long)]
+    pub utxos: PathBuf,
+
+    /// Files containing a signed transaction in hex, like the `tx` field of `sign`
+    #[clap(name = "tx", required = true)]
+    pub txs: Vec<PathBuf>,
+}
+
+/// An element of the `listunspent` result, only the outpoint is needed
+#[derive(Debug, Deserialize)]
+struct Utxo {
+    txid: Txid,
+    vout: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// The checked transactions, in the order given
+    pub transactions: Vec<Presigned>,
+
+    /// Transactions with spent inputs, to be signed again
+    pub need_resign: Vec<Txid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Presigned {
+    pub file: String,
+
+    pub txid: Txid,
+
+    /// All the inputs are unspent in the snapshot
+    pub valid: bool,
+
+    /// Inputs missing from the snapshot
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spent_inputs: Vec<OutPoint>,
+
+    /// The transaction can be included in the blocks following this height, or after this unix
+    /// timestamp as median time past
+    pub lock_time: u32,
+
+    pub lock_type: LockType,
+
+    /// False if every input has a final sequence, in which case the locktime is not enforced
+    pub lock_time_enabled: bool,
+}
+
+pub fn main(params: Params) -> Result<Output, Error> {
+    let unspent = load_outpoints(&params.utxos)?;
+
+    let mut transactions = vec![];
+    for path in params.txs.iter() {
+        let content = std::fs::read_to_string(path)?;
+        let tx: Transaction = deserialize_hex(content.trim())
+            .map_err(|e| Error::String(format!("invalid transaction {}: {e}", path.display())))?;
+        let spent_inputs: Vec<OutPoint> = tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .filter(|o| !unspent.contains(o))
+            .collect();
+        let lock_type = match tx.lock_time {
+            absolute::LockTime::Blocks(_) => LockType::Height,
+            absolute::LockTime::Seconds(_) => LockType::Time,
+        };
+        transactions.push(Presigned {
+            file: path.display().to_string(),
+            txid: tx.compute_txid(),
+            valid: spent_inputs.is_empty(),
+            spent_inputs,
+            lock_time: tx.lock_time.to_consensus_u32(),
+            lock_type,
+            lock_time_enabled: tx.is_lock_time_enabled(),
+        });
+    }
+    let need_resign = transactions
+        .iter()
+        .filter(|t| !t.valid)
+        .map(|t| t.txid)
+        .collect();
+    Ok(Output {
+        transactions,
+        need_resign,
+    })
+}
+
+/// The outpoints of the `listunspent` snapshot
+fn load_outpoints(path: &Path) -> Result<HashSet<OutPoint>, Error> {
+    let utxos: Vec<Utxo> = serde_json::from_reader(std::fs::File::open(path)?)
+        .map_err(|e| Error::String(format!("invalid utxos file {}: {e}", path.display())))?;
+    Ok(utxos
+        .into_iter()
+        .map(|u| OutPoint::new(u.txid, u.vout))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Params;
+    use crate::inheritance::{self, LockType};
+    use crate::Seed;
+    use bitcoin::Network;
+    use miniscript::{Descriptor, DescriptorPublicKey};
+    use std::path::Path;
+
+    const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
+    const DESCRIPTOR_TESTNET: &str = include_str!("../../wallet/descriptor_testnet");
+
+    #[test]
+    fn test_check_presigned() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let single = desc.into_single_descriptors().expect("test");
+        let utxo = |index, vout| {
+            let script_pubkey = single[0]
+                .at_derivation_index(index)
+                .expect("test")
+                .script_pubkey();
+            format!(
+                r#"{{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": {vout}, "scriptPubKey": "{script_pubkey:x}", "amount": 0.001}}"#
+            )
+        };
+        let dir = tempfile::tempdir().expect("test");
+        let write_snapshot = |name: &str, utxos: &[String]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, format!("[{}]", utxos.join(","))).expect("test");
+            path
+        };
+        let snapshot = write_snapshot("utxos.json", &[utxo(0, 0), utxo(1, 1)]);
+
+        let params = inheritance::Params {
+            utxos: snapshot.clone(),
+            prev_txs: None,
+            heir_address: Some(
+                "tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c"
+                    .parse()
+                    .expect("test"),
+            ),
+            heir_descriptor: None,
+            start: 1_800_000_000,
+            interval: 86_400,
+            count: 2,
+            fee_rate: 1.0,
+            wallet: crate::wallet::WalletParams {
+                network: Network::Testnet,
+                max: 10,
+                accounts: 1,
+                descriptor: vec![],
+            },
+            out_dir: Some(dir.path().to_path_buf()),
+            audit_log: None,
+            signed_outpoints: None,
+            allow_replace: false,
+        };
+        let bundle = inheritance::main(&seed, params).expect("test");
+        let txs: Vec<_> = bundle
+            .transactions
+            .iter()
+            .map(|t| dir.path().join(format!("{}.tx", t.lock_time)))
+            .collect();
+        let check = |utxos: &Path| {
+            let params = Params {
+                utxos: utxos.to_path_buf(),
+                txs: txs.clone(),
+            };
+            super::main(params).expect("test")
+        };
+
+        let output = check(&snapshot);
+        assert!(output.need_resign.is_empty());
+        assert_eq!(output.transactions.len(), 2);
+        let first = &output.transactions[0];
+        assert!(first.valid);
+        assert_eq!(first.txid, bundle.transactions[0].txid);
+        assert_eq!(first.lock_time, 1_800_000_000);
+        assert_eq!(first.lock_type, LockType::Time);
+        assert!(first.lock_time_enabled);
+
+        // one of the coins moved
+        let snapshot = write_snapshot("moved.json", &[utxo(1, 1), utxo(2, 2)]);
+        let output = check(&snapshot);
+        assert_eq!(output.need_resign.len(), 2);
+        assert_eq!(output.transactions[1].spent_inputs.len(), 1);
+        assert_eq!(output.transactions[1].spent_inputs[0].vout, 0);
+
+        // only the outpoints are needed
+        let outpoint = |vout| {
+            format!(
+                r#"{{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": {vout}}}"#
+            )
+        };
+        let snapshot = write_snapshot("outpoints.json", &[outpoint(0), outpoint(1)]);
+        assert!(check(&snapshot).need_resign.is_empty());
+    }
+}
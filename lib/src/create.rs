@@ -0,0 +1,837 @@
+use crate::import::{compute_descriptors, expand_descriptor};
+use crate::spendable::compute_finite_descriptors;
+use crate::wallet::WalletParams;
+use crate::{error::Error, seed::Seed};
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::key::Secp256k1;
+use bitcoin::{
+    absolute, transaction, Address, Amount, Network, OutPoint, Psbt, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Weight, WitnessVersion,
+};
+use clap::{Parser, ValueEnum};
+use miniscript::descriptor::{DefiniteDescriptorKey, DescriptorType};
+use miniscript::psbt::PsbtExt;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Takes a seed from standard input and creates a PSBT paying the given recipients, selecting
+/// the coins from a snapshot of the unspent outputs like the one returned by bitcoin core
+/// `listunspent`. Inputs and change are updated with the key origins of the standard
+/// descriptors, or of the given ones
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// Json file with the unspent outputs, objects with `txid`, `vout`, `scriptPubKey` and
+    /// `amount` in bitcoin like the ones returned by `bitcoin-cli listunspent`
+    pub utxos: PathBuf,
+
+    /// Directory of files with the previous transactions of the unspent outputs in hex, like the
+    /// ones returned by `bitcoin-cli getrawtransaction`. Needed to spend legacy outputs, and
+    /// segwit v0 outputs on mainnet, the ones without it are not selected
+    #[clap(long)]
+    pub prev_txs: Option<PathBuf>,
+
+    /// Recipient as `<address>:<satoshi>`, can be repeated
+    #[clap(long = "to", required = true, value_parser = recipient_parser)]
+    pub recipients: Vec<Recipient>,
+
+    /// Fee rate in sat/vB
+    #[clap(long)]
+    pub fee_rate: f64,
+
+    #[command(flatten)]
+    pub wallet: WalletParams,
+
+    /// Type of the descriptor receiving the change, in the account of the largest selected
+    /// input, taproot for the standard descriptors and the first given descriptor otherwise
+    #[clap(long)]
+    pub change_type: Option<ChangeType>,
+
+    /// Derivation index of the change address, like the next unused one reported by the online
+    /// wallet. The snapshot doesn't contain the spent addresses, so it can't be derived from it
+    #[clap(long)]
+    pub change_index: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub address: Address<NetworkUnchecked>,
+    pub amount: Amount,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChangeType {
+    Pkh,
+    ShWpkh,
+    Wpkh,
+    Tr,
+    Wsh,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// The created PSBT in base64
+    pub psbt: String,
+
+    /// Selected unspent outputs
+    pub inputs: Vec<OutPoint>,
+
+    /// Change address, missing if the change would be dust or not needed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+
+    /// The absolute fee of the tx in satoshi
+    pub fee: u64,
+
+    /// Estimated virtual size of the signed transaction
+    pub vsize: u64,
+
+    pub selection: Selection,
+
+    /// Unspent outputs in the snapshot not belonging to the descriptors, never selected
+    pub unknown_utxos: Vec<OutPoint>,
+
+    /// Unspent outputs of ours never selected because their previous transaction is missing
+    pub missing_prev_tx: Vec<OutPoint>,
+
+    /// Unspent outputs of ours never selected because spending them costs more than their value
+    /// at the fee rate
+    pub uneconomic_utxos: Vec<OutPoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub address: String,
+
+    pub amount: u64,
+
+    /// Account of the internal descriptor
+    pub account: u32,
+
+    /// Derivation index in the internal descriptor
+    pub index: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// Branch and bound found inputs matching the target without change
+    Bnb,
+
+    /// Largest inputs first, with change if not dust
+    LargestFirst,
+}
+
+/// An element of the `listunspent` result, other fields are ignored
+#[derive(Debug, Deserialize)]
+pub(crate) struct Utxo {
+    txid: Txid,
+    vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: ScriptBuf,
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    amount: Amount,
+}
+
+impl Utxo {
+    pub(crate) fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+}
+
+/// An unspent output of ours, with its effective value at the requested fee rate
+pub(crate) struct Candidate {
+    pub(crate) account: u32,
+    pub(crate) outpoint: OutPoint,
+    pub(crate) txout: TxOut,
+    pub(crate) prev_tx: Option<Transaction>,
+    pub(crate) descriptor: Descriptor<DefiniteDescriptorKey>,
+    pub(crate) weight: Weight,
+    pub(crate) effective_value: u64,
+}
+
+/// The unspent outputs of a snapshot, split by usability
+pub(crate) struct Candidates {
+    /// Spendable, sorted by descending effective value
+    pub(crate) candidates: Vec<Candidate>,
+
+    /// Not belonging to the descriptors
+    pub(crate) unknown_utxos: Vec<OutPoint>,
+
+    /// Ours, but without the previous transaction required to sign them
+    pub(crate) missing_prev_tx: Vec<OutPoint>,
+
+    /// Ours, but costing more in fee than their value
+    pub(crate) uneconomic_utxos: Vec<OutPoint>,
+}
+
+/// Version, locktime, inputs and outputs count, segwit marker and flag
+pub(crate) const TX_OVERHEAD: Weight = Weight::from_wu(42);
+
+/// Maximum number of nodes explored by branch and bound
+const BNB_TRIES: u32 = 100_000;
+
+pub fn main(seed: &Seed, params: Params) -> Result<Output, Error> {
+    let Params {
+        utxos,
+        prev_txs,
+        recipients,
+        fee_rate,
+        change_type,
+        change_index,
+        wallet:
+            WalletParams {
+                network,
+                max,
+                accounts,
+                descriptor,
+            },
+    } = params;
+    if !(fee_rate.is_finite() && fee_rate >= 1.0) {
+        return Err(Error::Other("fee rate must be at least 1 sat/vB"));
+    }
+    let utxos = load_utxos(&utxos)?;
+    let descriptors = account_descriptors(seed, network, accounts, &descriptor)?;
+    let prev_txs = match prev_txs {
+        Some(dir) => load_prev_txs(&dir)?,
+        None => HashMap::new(),
+    };
+    let Candidates {
+        candidates,
+        unknown_utxos,
+        missing_prev_tx,
+        uneconomic_utxos,
+    } = candidates(&descriptors, max, network, &utxos, &prev_txs, fee_rate)?;
+
+    // the weights of the change are the same in every account
+    let change_desc = change_descriptor(seed, network, &descriptor, change_type, 0)?
+        .at_derivation_index(change_index)?;
+    let change_weight = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: change_desc.script_pubkey(),
+    }
+    .weight();
+    let change_fee = fee(change_weight, fee_rate);
+    let change_spend_fee = fee(input_weight(&change_desc)?, fee_rate);
+
+    let mut outputs = vec![];
+    for recipient in recipients {
+        let script_pubkey = recipient.address.require_network(network)?.script_pubkey();
+        if recipient.amount < script_pubkey.minimal_non_dust() {
+            return Err(Error::String(format!(
+                "amount {} to {script_pubkey:x} is dust",
+                recipient.amount.to_sat()
+            )));
+        }
+        outputs.push(TxOut {
+            value: recipient.amount,
+            script_pubkey,
+        });
+    }
+
+    let sent: u64 = outputs.iter().map(|o| o.value.to_sat()).sum();
+    let outputs_weight = outputs.iter().map(TxOut::weight).sum::<Weight>();
+    let target = sent + fee(TX_OVERHEAD + outputs_weight, fee_rate);
+    let values: Vec<u64> = candidates.iter().map(|c| c.effective_value).collect();
+
+    let cost_of_change = change_fee + change_spend_fee;
+    let (selection, selected) = match bnb(&values, target, target + cost_of_change) {
+        Some(selected) => (Selection::Bnb, selected),
+        None => (Selection::LargestFirst, largest_first(&values, target)?),
+    };
+    let selected: Vec<&Candidate> = selected.iter().map(|i| &candidates[*i]).collect();
+
+    // candidates are sorted, the first selected is the largest
+    let change_account = selected[0].account;
+    let change_desc = change_descriptor(seed, network, &descriptor, change_type, change_account)?
+        .at_derivation_index(change_index)?;
+    let change_script = change_desc.script_pubkey();
+
+    let effective: u64 = selected.iter().map(|c| c.effective_value).sum();
+    let excess = effective - target;
+    let dust = change_script.minimal_non_dust().to_sat();
+    let change = if selection == Selection::LargestFirst && excess >= change_fee + dust {
+        let amount = excess - change_fee;
+        outputs.push(TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey: change_script.clone(),
+        });
+        let address = Address::from_script(&change_script, network)?.to_string();
+        Some(Change {
+            address,
+            amount,
+            account: change_account,
+            index: change_index,
+        })
+    } else {
+        None
+    };
+
+    let mut psbt = unsigned_psbt(
+        &selected,
+        outputs,
+        absolute::LockTime::ZERO,
+        Sequence::ENABLE_RBF_NO_LOCKTIME,
+    )?;
+    if change.is_some() {
+        let change_idx = psbt.outputs.len() - 1;
+        psbt.update_output_with_descriptor(change_idx, &change_desc)
+            .map_err(|e| Error::String(format!("change output: {e}")))?;
+    }
+
+    let sum_input: u64 = selected.iter().map(|c| c.txout.value.to_sat()).sum();
+    let sum_output: u64 = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|o| o.value.to_sat())
+        .sum();
+    let weight = TX_OVERHEAD
+        + selected.iter().map(|c| c.weight).sum::<Weight>()
+        + psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(TxOut::weight)
+            .sum::<Weight>();
+
+    Ok(Output {
+        psbt: psbt.to_string(),
+        inputs: selected.iter().map(|c| c.outpoint).collect(),
+        change,
+        fee: sum_input - sum_output,
+        vsize: weight.to_vbytes_ceil(),
+        selection,
+        unknown_utxos,
+        missing_prev_tx,
+        uneconomic_utxos,
+    })
+}
+
+/// Parse the `listunspent` snapshot
+pub(crate) fn load_utxos(path: &Path) -> Result<Vec<Utxo>, Error> {
+    serde_json::from_reader(std::fs::File::open(path)?)
+        .map_err(|e| Error::String(format!("invalid utxos file {}: {e}", path.display())))
+}
+
+/// Load the previous transactions in hex from every file of the directory
+pub(crate) fn load_prev_txs(dir: &Path) -> Result<HashMap<Txid, Transaction>, Error> {
+    let mut prev_txs = HashMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hex = std::fs::read_to_string(&path)?;
+        let tx: Transaction = deserialize_hex(hex.trim()).map_err(|e| {
+            Error::String(format!("invalid transaction in {}: {e}", path.display()))
+        })?;
+        prev_txs.insert(tx.compute_txid(), tx);
+    }
+    Ok(prev_txs)
+}
+
+/// The single path descriptors of every account, standard or the given ones
+pub(crate) fn account_descriptors(
+    seed: &Seed,
+    network: Network,
+    accounts: u32,
+    descriptor: &[String],
+) -> Result<Vec<(u32, Descriptor<DescriptorPublicKey>)>, Error> {
+    let secp = Secp256k1::new();
+    let mut descriptors = vec![];
+    for account in 0..accounts {
+        let finite =
+            compute_finite_descriptors(seed, network, &secp, account..account + 1, descriptor)?;
+        descriptors.extend(finite.into_iter().map(|d| (account, d)));
+    }
+    Ok(descriptors)
+}
+
+/// Unspent outputs of the snapshot belonging to the descriptors and worth spending at the fee
+/// rate. The previous transaction is required for legacy outputs, because PSBT inputs spending
+/// them can't have the `witness_utxo`, and for segwit v0 outputs on mainnet, where `sign`
+/// requires it by default
+pub(crate) fn candidates(
+    descriptors: &[(u32, Descriptor<DescriptorPublicKey>)],
+    max: u32,
+    network: Network,
+    utxos: &[Utxo],
+    prev_txs: &HashMap<Txid, Transaction>,
+    fee_rate: f64,
+) -> Result<Candidates, Error> {
+    let mut script_pubkeys = HashMap::new();
+    for (account, desc) in descriptors.iter() {
+        for i in 0..max {
+            let definite = desc.at_derivation_index(i)?;
+            script_pubkeys.insert(definite.script_pubkey(), (*account, definite));
+        }
+    }
+
+    let mut candidates = vec![];
+    let mut unknown_utxos = vec![];
+    let mut missing_prev_tx = vec![];
+    let mut uneconomic_utxos = vec![];
+    for utxo in utxos {
+        let outpoint = utxo.outpoint();
+        let (account, descriptor) = match script_pubkeys.get(&utxo.script_pubkey) {
+            Some(found) => found.clone(),
+            None => {
+                unknown_utxos.push(outpoint);
+                continue;
+            }
+        };
+        let txout = TxOut {
+            value: utxo.amount,
+            script_pubkey: utxo.script_pubkey.clone(),
+        };
+        let prev_tx = prev_txs.get(&utxo.txid).cloned();
+        if let Some(prev_tx) = prev_tx.as_ref() {
+            if prev_tx.output.get(utxo.vout as usize) != Some(&txout) {
+                return Err(Error::String(format!(
+                    "previous transaction of {outpoint} doesn't match the snapshot"
+                )));
+            }
+        }
+        let prev_tx_required = match descriptor.desc_type().segwit_version() {
+            None => true,
+            Some(WitnessVersion::V0) => network == Network::Bitcoin,
+            Some(_) => false,
+        };
+        if prev_tx_required && prev_tx.is_none() {
+            missing_prev_tx.push(outpoint);
+            continue;
+        }
+        let weight = input_weight(&descriptor)?;
+        // inputs costing more than their value are never useful
+        let effective_value = match utxo.amount.to_sat().checked_sub(fee(weight, fee_rate)) {
+            Some(value) if value > 0 => value,
+            _ => {
+                uneconomic_utxos.push(outpoint);
+                continue;
+            }
+        };
+        candidates.push(Candidate {
+            account,
+            outpoint,
+            txout,
+            prev_tx,
+            descriptor,
+            weight,
+            effective_value,
+        });
+    }
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.effective_value));
+    Ok(Candidates {
+        candidates,
+        unknown_utxos,
+        missing_prev_tx,
+        uneconomic_utxos,
+    })
+}
+
+/// A PSBT spending the candidates, with inputs updated with the key origins of the descriptors,
+/// the previous transactions and, for segwit inputs, the spent outputs
+pub(crate) fn unsigned_psbt(
+    selected: &[&Candidate],
+    outputs: Vec<TxOut>,
+    lock_time: absolute::LockTime,
+    sequence: Sequence,
+) -> Result<Psbt, Error> {
+    let tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time,
+        input: selected
+            .iter()
+            .map(|c| TxIn {
+                previous_output: c.outpoint,
+                sequence,
+                ..Default::default()
+            })
+            .collect(),
+        output: outputs,
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).expect("inputs are empty");
+    for (input_idx, candidate) in selected.iter().enumerate() {
+        let input = &mut psbt.inputs[input_idx];
+        input.non_witness_utxo = candidate.prev_tx.clone();
+        if candidate.descriptor.desc_type().segwit_version().is_some() {
+            input.witness_utxo = Some(candidate.txout.clone());
+        }
+        psbt.update_input_with_descriptor(input_idx, &candidate.descriptor)
+            .map_err(|e| Error::String(format!("input #{input_idx}: {e}")))?;
+    }
+    Ok(psbt)
+}
+
+/// The internal descriptor of the account of the requested type
+fn change_descriptor(
+    seed: &Seed,
+    network: Network,
+    custom: &[String],
+    change_type: Option<ChangeType>,
+    account: u32,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    let secp = Secp256k1::new();
+    let descriptors = if custom.is_empty() {
+        compute_descriptors(seed, network, &secp, account)
+    } else {
+        custom
+            .iter()
+            .map(|d| expand_descriptor(seed, network, &secp, account, d))
+            .collect::<Result<_, _>>()?
+    };
+    let change_type = match (change_type, custom.is_empty()) {
+        (Some(change_type), _) => Some(change_type),
+        (None, true) => Some(ChangeType::Tr),
+        (None, false) => None,
+    };
+    let desc = descriptors
+        .into_iter()
+        .find(|d| change_type.is_none_or(|t| t.matches(d.desc_type())))
+        .ok_or(Error::Other("no descriptor of the requested change type"))?;
+    desc.into_single_descriptors()?
+        .into_iter()
+        .nth(1)
+        .ok_or(Error::Other(
+            "change descriptor must be multipath like <0;1>",
+        ))
+}
+
+impl ChangeType {
+    fn matches(&self, desc_type: DescriptorType) -> bool {
+        match self {
+            ChangeType::Pkh => desc_type == DescriptorType::Pkh,
+            ChangeType::ShWpkh => desc_type == DescriptorType::ShWpkh,
+            ChangeType::Wpkh => desc_type == DescriptorType::Wpkh,
+            ChangeType::Tr => desc_type == DescriptorType::Tr,
+            ChangeType::Wsh => {
+                matches!(
+                    desc_type,
+                    DescriptorType::Wsh | DescriptorType::WshSortedMulti
+                )
+            }
+        }
+    }
+}
+
+/// Weight of an input spending the descriptor once satisfied
+pub(crate) fn input_weight(desc: &Descriptor<DefiniteDescriptorKey>) -> Result<Weight, Error> {
+    Ok(TxIn::default().segwit_weight() + desc.max_weight_to_satisfy()?)
+}
+
+pub(crate) fn fee(weight: Weight, fee_rate: f64) -> u64 {
+    (weight.to_wu() as f64 * fee_rate / 4.0).ceil() as u64
+}
+
+/// Branch and bound search of the inputs with the smallest total in `target..=upper`, values
+/// must be sorted in descending order
+fn bnb(values: &[u64], target: u64, upper: u64) -> Option<Vec<usize>> {
+    let mut remaining = vec![0; values.len() + 1];
+    for i in (0..values.len()).rev() {
+        remaining[i] = remaining[i + 1] + values[i];
+    }
+    let mut search = Bnb {
+        values,
+        remaining,
+        target,
+        upper,
+        tries: 0,
+        selected: vec![],
+        best: None,
+    };
+    search.explore(0, 0);
+    search.best.map(|(_, selected)| selected)
+}
+
+struct Bnb<'a> {
+    values: &'a [u64],
+    /// Sum of the values from the index to the end
+    remaining: Vec<u64>,
+    target: u64,
+    upper: u64,
+    tries: u32,
+    selected: Vec<usize>,
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl Bnb<'_> {
+    fn explore(&mut self, idx: usize, sum: u64) {
+        self.tries += 1;
+        if self.tries > BNB_TRIES || sum > self.upper {
+            return;
+        }
+        if sum >= self.target {
+            if self.best.as_ref().is_none_or(|(best, _)| sum < *best) {
+                self.best = Some((sum, self.selected.clone()));
+            }
+            return;
+        }
+        if idx == self.values.len() || sum + self.remaining[idx] < self.target {
+            return;
+        }
+        self.selected.push(idx);
+        self.explore(idx + 1, sum + self.values[idx]);
+        self.selected.pop();
+        self.explore(idx + 1, sum);
+    }
+}
+
+/// Select the largest values until the target is reached, values must be sorted in descending
+/// order
+fn largest_first(values: &[u64], target: u64) -> Result<Vec<usize>, Error> {
+    let mut sum = 0;
+    for (i, value) in values.iter().enumerate() {
+        sum += value;
+        if sum >= target {
+            return Ok((0..=i).collect());
+        }
+    }
+    Err(Error::String(format!(
+        "insufficient funds, {sum} available after fees but {target} needed"
+    )))
+}
+
+fn recipient_parser(s: &str) -> Result<Recipient, String> {
+    let (address, amount) = s
+        .rsplit_once(':')
+        .ok_or("recipient must be <address>:<satoshi>")?;
+    Ok(Recipient {
+        address: address.parse().map_err(|e| format!("{e}"))?,
+        amount: Amount::from_sat(amount.parse().map_err(|e| format!("{e}"))?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bnb, largest_first, Params, Selection};
+    use crate::import::compute_descriptors;
+    use crate::{sign, Seed};
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::key::Secp256k1;
+    use bitcoin::{absolute, transaction, Amount, Network, Psbt, Transaction, TxOut};
+    use miniscript::{Descriptor, DescriptorPublicKey};
+    use std::io::Write;
+    use std::str::FromStr;
+    use tempfile::NamedTempFile;
+
+    const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
+    const DESCRIPTOR_TESTNET: &str = include_str!("../../wallet/descriptor_testnet");
+
+    #[test]
+    fn test_coin_selection() {
+        let values = [50, 40, 30, 20, 10];
+        assert_eq!(bnb(&values, 60, 60), Some(vec![0, 4]));
+        assert_eq!(bnb(&values, 60, 65), Some(vec![0, 4]));
+        assert_eq!(bnb(&values, 95, 100), Some(vec![0, 1, 4]));
+        assert_eq!(bnb(&values, 96, 98), None);
+        assert_eq!(bnb(&values, 200, 300), None);
+
+        assert_eq!(largest_first(&values, 60).expect("test"), vec![0, 1]);
+        assert_eq!(largest_first(&values, 150).expect("test").len(), 5);
+        assert!(largest_first(&values, 151).is_err());
+    }
+
+    #[test]
+    fn test_create() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let single = desc.into_single_descriptors().expect("test");
+        let utxo = |desc: &Descriptor<DescriptorPublicKey>, index, vout, amount| {
+            let script_pubkey = desc
+                .at_derivation_index(index)
+                .expect("test")
+                .script_pubkey();
+            format!(
+                r#"{{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": {vout}, "scriptPubKey": "{script_pubkey:x}", "amount": {amount}, "confirmations": 6}}"#
+            )
+        };
+        let foreign = r#"{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": 3, "scriptPubKey": "00147cc19fbb961bb00f8e5630474e23cf3c4984b82f", "amount": 1.0}"#;
+        let snapshot = format!(
+            "[{}, {}, {}, {foreign}]",
+            utxo(&single[0], 0, 0, "0.001"),
+            utxo(&single[0], 1, 1, "0.0005"),
+            utxo(&single[1], 0, 2, "0.0002"),
+        );
+        let mut f = NamedTempFile::new().expect("test");
+        f.as_file_mut()
+            .write_all(snapshot.as_bytes())
+            .expect("Unable to write data");
+        let create = |to: &str| {
+            let params = Params {
+                utxos: f.path().to_path_buf(),
+                prev_txs: None,
+                recipients: vec![super::recipient_parser(to).expect("test")],
+                fee_rate: 2.0,
+                wallet: crate::wallet::WalletParams {
+                    network,
+                    max: 10,
+                    accounts: 1,
+                    descriptor: vec![],
+                },
+                change_type: None,
+                change_index: 1,
+            };
+            super::main(&seed, params)
+        };
+
+        let output = create("tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c:60000").expect("test");
+        assert_eq!(output.selection, Selection::LargestFirst);
+        assert_eq!(output.inputs.len(), 1);
+        assert_eq!(output.inputs[0].vout, 0);
+        assert_eq!(output.unknown_utxos.len(), 1);
+        let change = output.change.as_ref().expect("test");
+        assert_eq!(change.account, 0);
+        assert_eq!(change.index, 1);
+        assert_eq!(change.amount + output.fee + 60_000, 100_000);
+
+        let psbt = Psbt::from_str(&output.psbt).expect("test");
+        assert!(!psbt.inputs[0].tap_key_origins.is_empty());
+        assert!(!psbt.outputs[1].tap_key_origins.is_empty());
+
+        let mut psbt_file = NamedTempFile::new().expect("test");
+        psbt_file
+            .as_file_mut()
+            .write_all(output.psbt.as_bytes())
+            .expect("Unable to write data");
+        let params = sign::test::sign_params(psbt_file.path(), network);
+        let signed = sign::main(&seed, params).expect("test").remove(0);
+        assert_eq!(signed.fee, output.fee);
+        assert_eq!(signed.bal, -60_000 - output.fee as i64);
+        let vsize = signed.vsize.expect("finalized");
+        assert!(vsize <= output.vsize, "{vsize} {}", output.vsize);
+        assert!(signed.fee_rate.expect("finalized") >= 2.0);
+
+        // spending the whole effective value of the two largest inputs doesn't need change
+        let output = create("tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c:149600").expect("test");
+        assert_eq!(output.selection, Selection::Bnb);
+        assert!(output.change.is_none());
+        assert_eq!(output.inputs.len(), 2);
+
+        assert!(create("tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c:200000").is_err());
+
+        // the change goes to the account of the largest input
+        let secp = Secp256k1::new();
+        let account_1 = compute_descriptors(&seed, network, &secp, 1).remove(3);
+        let account_1 = account_1.into_single_descriptors().expect("test");
+        let snapshot = format!(
+            "[{}, {}]",
+            utxo(&single[0], 0, 0, "0.0005"),
+            utxo(&account_1[0], 0, 1, "0.001"),
+        );
+        std::fs::write(f.path(), snapshot).expect("test");
+        let params = Params {
+            utxos: f.path().to_path_buf(),
+            prev_txs: None,
+            recipients: vec![super::recipient_parser(
+                "tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c:60000",
+            )
+            .expect("test")],
+            fee_rate: 2.0,
+            wallet: crate::wallet::WalletParams {
+                network,
+                max: 10,
+                accounts: 2,
+                descriptor: vec![],
+            },
+            change_type: None,
+            change_index: 3,
+        };
+        let output = super::main(&seed, params).expect("test");
+        let change = output.change.as_ref().expect("test");
+        assert_eq!(change.account, 1);
+        let change_address = account_1[1]
+            .at_derivation_index(3)
+            .expect("test")
+            .address(network)
+            .expect("test");
+        assert_eq!(change.address, change_address.to_string());
+    }
+
+    #[test]
+    fn test_prev_txs() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let secp = Secp256k1::new();
+        let descriptors = compute_descriptors(&seed, network, &secp, 0);
+        let script_pubkey = |i: usize| {
+            descriptors[i]
+                .clone()
+                .into_single_descriptors()
+                .expect("test")
+                .remove(0)
+                .at_derivation_index(0)
+                .expect("test")
+                .script_pubkey()
+        };
+        let pkh = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: script_pubkey(0),
+        };
+        let prev_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![pkh.clone()],
+        };
+        let snapshot = format!(
+            r#"[{{"txid": "{}", "vout": 0, "scriptPubKey": "{:x}", "amount": 0.001}}, {{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": 0, "scriptPubKey": "{:x}", "amount": 0.0005}}]"#,
+            prev_tx.compute_txid(),
+            pkh.script_pubkey,
+            script_pubkey(3),
+        );
+        let f = NamedTempFile::new().expect("test");
+        std::fs::write(f.path(), &snapshot).expect("test");
+        let dir = tempfile::tempdir().expect("test");
+        let create = |prev_tx: Option<&Transaction>| {
+            if let Some(prev_tx) = prev_tx {
+                std::fs::write(dir.path().join("prev.hex"), serialize_hex(prev_tx)).expect("test");
+            }
+            let params = Params {
+                utxos: f.path().to_path_buf(),
+                prev_txs: prev_tx.map(|_| dir.path().to_path_buf()),
+                recipients: vec![super::recipient_parser(
+                    "tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c:30000",
+                )
+                .expect("test")],
+                fee_rate: 2.0,
+                wallet: crate::wallet::WalletParams {
+                    network,
+                    max: 10,
+                    accounts: 1,
+                    descriptor: vec![],
+                },
+                change_type: None,
+                change_index: 0,
+            };
+            super::main(&seed, params)
+        };
+
+        // the legacy output can't be spent without the previous transaction
+        let output = create(None).expect("test");
+        assert_eq!(output.missing_prev_tx.len(), 1);
+        assert_eq!(output.missing_prev_tx[0].txid, prev_tx.compute_txid());
+        assert_ne!(output.inputs[0].txid, prev_tx.compute_txid());
+
+        let output = create(Some(&prev_tx)).expect("test");
+        assert!(output.missing_prev_tx.is_empty());
+        assert_eq!(output.inputs[0].txid, prev_tx.compute_txid());
+        let psbt = Psbt::from_str(&output.psbt).expect("test");
+        assert_eq!(psbt.inputs[0].non_witness_utxo.as_ref(), Some(&prev_tx));
+        assert!(psbt.inputs[0].witness_utxo.is_none());
+
+        let mut psbt_file = NamedTempFile::new().expect("test");
+        psbt_file
+            .as_file_mut()
+            .write_all(output.psbt.as_bytes())
+            .expect("Unable to write data");
+        let params = sign::test::sign_params(psbt_file.path(), network);
+        let signed = sign::main(&seed, params).expect("test").remove(0);
+        assert!(signed.tx.is_some());
+
+        // the amount in the snapshot doesn't match the previous transaction
+        std::fs::write(f.path(), snapshot.replace("0.001", "0.002")).expect("test");
+        assert!(create(Some(&prev_tx)).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::import::{account_parser, bip48_path, compute_descriptors, xpub_with_origin};
+use crate::{error::Error, seed::Seed};
+use bitcoin::{bip32::DerivationPath, key::Secp256k1, Network};
+use clap::Parser;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+
+/// Takes a seed from standard input and returns the standard descriptors (bip 44, 49, 84, 86)
+/// and the multisig cosigner key (bip 48), or the xpub with origin at the given custom derivation path
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// Custom derivation path, like `0h/1h`. If missing the standard descriptors are returned
+    pub path: Option<DerivationPath>,
+
+    /// Bitcoin Network. bitcoin,testnet,signet are possible values
+    #[clap(short, long, env)]
+    pub network: Network,
+
+    /// Account number used in the derivation path of the standard descriptors, like in `84'/0'/account'`
+    #[clap(short, long, default_value_t = 0, value_parser = account_parser())]
+    pub account: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub singlesig: Option<SingleSig>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultiSig>,
+
+    /// Xpub with origin at the custom derivation path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SingleSig {
+    pub bip44_pkh: Descriptors,
+    pub bip49_shwpkh: Descriptors,
+    pub bip84_wpkh: Descriptors,
+    pub bip86_tr: Descriptors,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultiSig {
+    /// Xpub with origin at `48'/coin'/account'/2'` to be used as cosigner key in
+    /// `wsh(sortedmulti(...))` descriptors
+    pub bip48_wsh: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Descriptors {
+    /// Descriptor with `<0;1>` multipath, for both external and internal addresses
+    pub multipath: String,
+
+    /// Descriptor for receiving addresses
+    pub external: String,
+
+    /// Descriptor for change addresses
+    pub internal: String,
+}
+
+pub fn main(seed: &Seed, params: Params) -> Result<Output, Error> {
+    let Params {
+        path,
+        network,
+        account,
+    } = params;
+    let secp = Secp256k1::new();
+
+    if let Some(path) = path {
+        return Ok(Output {
+            singlesig: None,
+            multisig: None,
+            custom: Some(xpub_with_origin(seed, network, &secp, path)),
+        });
+    }
+
+    let [bip44_pkh, bip49_shwpkh, bip84_wpkh, bip86_tr]: [Descriptors; 4] =
+        compute_descriptors(seed, network, &secp, account)
+            .into_iter()
+            .map(Descriptors::try_from)
+            .collect::<Result<Vec<_>, _>>()?
+            .try_into()
+            .expect("compute_descriptors returns bip 44, 49, 84, 86");
+
+    Ok(Output {
+        singlesig: Some(SingleSig {
+            bip44_pkh,
+            bip49_shwpkh,
+            bip84_wpkh,
+            bip86_tr,
+        }),
+        multisig: Some(MultiSig {
+            bip48_wsh: xpub_with_origin(seed, network, &secp, bip48_path(network, account, 2)),
+        }),
+        custom: None,
+    })
+}
+
+impl TryFrom<Descriptor<DescriptorPublicKey>> for Descriptors {
+    type Error = Error;
+
+    fn try_from(desc: Descriptor<DescriptorPublicKey>) -> Result<Self, Self::Error> {
+        let mut singles = desc.clone().into_single_descriptors()?.into_iter();
+        let mut next = || {
+            singles
+                .next()
+                .map(|d| d.to_string())
+                .ok_or(Error::Other("descriptor without external and internal"))
+        };
+        Ok(Descriptors {
+            multipath: desc.to_string(),
+            external: next()?,
+            internal: next()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::Network;
+
+    use crate::Seed;
+
+    use super::Params;
+
+    const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
+    const DESCRIPTOR_MAINNET: &str = include_str!("../../wallet/descriptor_mainnet");
+    const DESCRIPTOR_MAINNET_EXTERNAL: &str =
+        include_str!("../../wallet/descriptor_mainnet_external");
+    const DESCRIPTOR_MAINNET_INTERNAL: &str =
+        include_str!("../../wallet/descriptor_mainnet_internal");
+    const DESCRIPTOR_TESTNET: &str = include_str!("../../wallet/descriptor_testnet");
+    const DESCRIPTOR_TESTNET_EXTERNAL: &str =
+        include_str!("../../wallet/descriptor_testnet_external");
+    const DESCRIPTOR_TESTNET_INTERNAL: &str =
+        include_str!("../../wallet/descriptor_testnet_internal");
+
+    #[test]
+    fn test_derive() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+
+        let params = Params {
+            path: None,
+            network: Network::Bitcoin,
+            account: 0,
+        };
+        let result = super::main(&seed, params).expect("test");
+        assert!(result.custom.is_none());
+        let tr = result.singlesig.expect("test").bip86_tr;
+        assert_eq!(tr.multipath, DESCRIPTOR_MAINNET);
+        assert_eq!(tr.external, DESCRIPTOR_MAINNET_EXTERNAL);
+        assert_eq!(tr.internal, DESCRIPTOR_MAINNET_INTERNAL);
+
+        let params = Params {
+            path: None,
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(&seed, params).expect("test");
+        let singlesig = result.singlesig.expect("test");
+        assert_eq!(singlesig.bip86_tr.multipath, DESCRIPTOR_TESTNET);
+        assert_eq!(singlesig.bip86_tr.external, DESCRIPTOR_TESTNET_EXTERNAL);
+        assert_eq!(singlesig.bip86_tr.internal, DESCRIPTOR_TESTNET_INTERNAL);
+        assert!(singlesig
+            .bip44_pkh
+            .multipath
+            .starts_with("pkh([01e0b4da/44'/1'/0']"));
+        assert!(singlesig
+            .bip49_shwpkh
+            .external
+            .starts_with("sh(wpkh([01e0b4da/49'/1'/0']"));
+        assert!(singlesig
+            .bip84_wpkh
+            .internal
+            .starts_with("wpkh([01e0b4da/84'/1'/0']"));
+    }
+
+    #[test]
+    fn test_derive_custom() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = Params {
+            path: Some("0h/1h".parse().expect("test")),
+            network: Network::Testnet,
+            account: 0,
+        };
+        let result = super::main(&seed, params).expect("test");
+        assert!(result.singlesig.is_none());
+        assert_eq!(result.custom.expect("test"), "[01e0b4da/0'/1']tpubDBteAN9SBvfyvs8raNRRMv3uZf371jGbTUT5CcjR1HzWyByYGnhfRz5PQV6mcg2s1EKtZAnC6EW29NGcQzBBNhKW6VMnmZngcT6kukRGQ6v");
+    }
+
+    #[test]
+    fn test_derive_account_multisig() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = Params {
+            path: None,
+            network: Network::Testnet,
+            account: 7,
+        };
+        let result = super::main(&seed, params).expect("test");
+        let singlesig = result.singlesig.expect("test");
+        assert!(singlesig
+            .bip86_tr
+            .multipath
+            .starts_with("tr([01e0b4da/86'/1'/7']"));
+        let multisig = result.multisig.expect("test");
+        assert!(multisig
+            .bip48_wsh
+            .starts_with("[01e0b4da/48'/1'/7'/2']tpub"));
+    }
+}
@@ -8,7 +8,7 @@ pub enum Error {
 
     Seed(crate::seed::SeedError),
 
-    ExtractTx(bitcoin::psbt::ExtractTxError),
+    ExtractTx(Box<bitcoin::psbt::ExtractTxError>),
 
     Io(std::io::Error),
 
@@ -20,9 +20,14 @@ pub enum Error {
 
     Miniscript(miniscript::Error),
 
+    Finalize(Box<miniscript::psbt::Error>),
+
     Descriptor(miniscript::descriptor::ConversionError),
 
     Parse(bitcoin::address::ParseError),
+
+    /// A rule of the spending policy is violated
+    Policy(String),
 }
 
 impl From<std::io::Error> for Error {
@@ -51,7 +56,7 @@ impl From<bitcoin::address::FromScriptError> for Error {
 
 impl From<bitcoin::psbt::ExtractTxError> for Error {
     fn from(e: bitcoin::psbt::ExtractTxError) -> Self {
-        Error::ExtractTx(e)
+        Error::ExtractTx(Box::new(e))
     }
 }
 
@@ -67,6 +72,12 @@ impl From<miniscript::Error> for Error {
     }
 }
 
+impl From<miniscript::psbt::Error> for Error {
+    fn from(e: miniscript::psbt::Error) -> Self {
+        Error::Finalize(Box::new(e))
+    }
+}
+
 impl From<miniscript::descriptor::ConversionError> for Error {
     fn from(e: miniscript::descriptor::ConversionError) -> Self {
         Error::Descriptor(e)
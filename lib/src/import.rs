@@ -7,11 +7,11 @@ use bitcoin::{
     Network,
 };
 use clap::Parser;
-use miniscript::{Descriptor, DescriptorPublicKey};
+use miniscript::{Descriptor, DescriptorPublicKey, ForEachKey};
 use serde::{Deserialize, Serialize};
 
 /// Takes a seed from standard input and return a command string to import
-/// bip 44, 49, 84, 86 wallets in bitcoin core as watch-only
+/// bip 44, 49, 84, 86 wallets, or the given descriptors, in bitcoin core as watch-only
 ///
 /// The json output is base64 encoded so that `'` are not an issue
 #[derive(Parser, Debug)]
@@ -24,12 +24,33 @@ pub struct Params {
     /// The wallet name to be created in bitcoin core
     #[clap(short, long)]
     pub wallet_name: String,
+
+    /// Account number used in the derivation path of the descriptors, like in `84'/0'/account'`
+    #[clap(short, long, default_value_t = 0, value_parser = account_parser())]
+    pub account: u32,
+
+    /// Descriptors imported instead of the standard ones, like an inheritance miniscript
+    /// `wsh(or_d(pk(@me),and_v(v:pk(HEIR/<0;1>/*),older(N))))`. `@me` is replaced with the key
+    /// of the seed `[fingerprint/48'/coin'/account'/2']xpub/<0;1>/*`, with `1'` instead of `2'`
+    /// in `sh(wsh())`. `@me` is refused in other descriptor types
+    #[clap(short, long)]
+    pub descriptor: Vec<String>,
 }
 
 pub fn main(seed: &Seed, params: Params) -> Result<String, Error> {
     let core_net = params.network.to_core_arg();
     let name = params.wallet_name.clone();
-    let r = core_import_json(seed, params.network)?;
+    let r = if params.descriptor.is_empty() {
+        core_import_json(seed, params.network, params.account)?
+    } else {
+        let secp = Secp256k1::new();
+        let descriptors = params
+            .descriptor
+            .iter()
+            .map(|d| expand_descriptor(seed, params.network, &secp, params.account, d))
+            .collect::<Result<_, _>>()?;
+        import_descriptors(descriptors)?
+    };
     let import = serde_json::to_string(&r).expect("doesn't contain non-string key");
     let import_encoded = BASE64_STANDARD.encode(import);
     let s1 = format!("bitcoin-cli -chain={core_net} -named createwallet wallet_name=\"{name}\" blank=true disable_private_keys=true");
@@ -38,9 +59,13 @@ pub fn main(seed: &Seed, params: Params) -> Result<String, Error> {
     Ok(format!("{s1}\n{s2}\n{s3}"))
 }
 
-pub fn core_import_json(seed: &Seed, network: Network) -> Result<Vec<ImportElement>, Error> {
+pub fn core_import_json(
+    seed: &Seed,
+    network: Network,
+    account: u32,
+) -> Result<Vec<ImportElement>, Error> {
     let secp = Secp256k1::new();
-    let descriptors = compute_descriptors(seed, network, &secp);
+    let descriptors = compute_descriptors(seed, network, &secp, account);
     import_descriptors(descriptors)
 }
 
@@ -77,13 +102,11 @@ fn single_desc(
     secp: &Secp256k1<All>,
     bip: u8,
     kind: &str,
+    account: u32,
 ) -> Descriptor<DescriptorPublicKey> {
-    let network_path = match network {
-        Network::Bitcoin => 0,
-        _ => 1,
-    };
-    let path = format!("{bip}'/{network_path}'/0'");
-    let path: DerivationPath = path.parse().expect("static path");
+    let network_path = coin_type(network);
+    let path = format!("{bip}'/{network_path}'/{account}'");
+    let path: DerivationPath = path.parse().expect("account is less than 2^31");
     let xpub_with_origin = xpub_with_origin(seed, network, secp, path);
     let final_parenthesis = if kind.contains('(') { ")" } else { "" };
     let desc_str = format!("{kind}({xpub_with_origin}/<0;1>/*){final_parenthesis}");
@@ -91,7 +114,21 @@ fn single_desc(
     desc
 }
 
-fn xpub_with_origin(
+fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    }
+}
+
+/// Multisig derivation path `48'/coin'/account'/script_type'`, script type 2 is for native segwit
+pub(crate) fn bip48_path(network: Network, account: u32, script_type: u32) -> DerivationPath {
+    let coin = coin_type(network);
+    let path = format!("48'/{coin}'/{account}'/{script_type}'");
+    path.parse().expect("account is less than 2^31")
+}
+
+pub(crate) fn xpub_with_origin(
     seed: &Seed,
     network: Network,
     secp: &Secp256k1<All>,
@@ -110,15 +147,72 @@ pub(crate) fn compute_descriptors(
     seed: &Seed,
     network: Network,
     secp: &Secp256k1<All>,
+    account: u32,
 ) -> Vec<Descriptor<DescriptorPublicKey>> {
-    let bip84 = single_desc(seed, network, secp, 84, "wpkh");
-    let bip86 = single_desc(seed, network, secp, 86, "tr");
-    let bib49 = single_desc(seed, network, secp, 49, "sh(wpkh");
-    let bib44 = single_desc(seed, network, secp, 44, "pkh");
+    let bip84 = single_desc(seed, network, secp, 84, "wpkh", account);
+    let bip86 = single_desc(seed, network, secp, 86, "tr", account);
+    let bib49 = single_desc(seed, network, secp, 49, "sh(wpkh", account);
+    let bib44 = single_desc(seed, network, secp, 44, "pkh", account);
 
     vec![bib44, bib49, bip84, bip86]
 }
 
+/// Placeholder for the key of the seed in the descriptors given by the user
+pub(crate) const ME: &str = "@me";
+
+/// Parse a descriptor given by the user replacing `@me` with the bip48 key of the seed, the
+/// result must contain a key of the seed.
+///
+/// Bip48 keys are defined only for multisig scripts, `2'` for `wsh()` and `1'` for
+/// `sh(wsh())`, so `@me` is refused in other descriptors like `tr()`
+pub(crate) fn expand_descriptor(
+    seed: &Seed,
+    network: Network,
+    secp: &Secp256k1<All>,
+    account: u32,
+    desc: &str,
+) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+    let desc = if desc.contains(ME) {
+        let trimmed = desc.trim_start();
+        let script_type = if trimmed.starts_with("wsh(") {
+            2
+        } else if trimmed.starts_with("sh(wsh(") {
+            1
+        } else {
+            return Err(Error::String(format!(
+                "{ME} is a bip48 key, allowed only in wsh() and sh(wsh()) descriptors"
+            )));
+        };
+        let path = bip48_path(network, account, script_type);
+        let me = format!("{}/<0;1>/*", xpub_with_origin(seed, network, secp, path));
+        desc.replace(ME, &me)
+    } else {
+        desc.to_string()
+    };
+    let desc: Descriptor<DescriptorPublicKey> = desc.parse()?;
+    let fingerprint = seed.fingerprint(secp);
+    if !desc.for_any_key(|k| k.master_fingerprint() == fingerprint) {
+        return Err(Error::String(format!(
+            "descriptor {desc} doesn't contain keys of the seed"
+        )));
+    }
+    Ok(desc)
+}
+
+/// Accounts are hardened derivation steps, thus must be less than 2^31
+pub(crate) fn account_parser() -> clap::builder::RangedI64ValueParser<u32> {
+    clap::value_parser!(u32).range(..0x8000_0000)
+}
+
+/// Maximum number of accounts scanned, every account multiplies the descriptors and the addresses
+/// generated
+pub(crate) const MAX_ACCOUNTS: u32 = 1000;
+
+/// Number of accounts scanned from account 0, at least one and at most [`MAX_ACCOUNTS`]
+pub(crate) fn accounts_parser() -> clap::builder::RangedI64ValueParser<u32> {
+    clap::value_parser!(u32).range(1..=MAX_ACCOUNTS as i64)
+}
+
 #[cfg(test)]
 mod test {
     use bitcoin::key::Secp256k1;
@@ -139,6 +233,8 @@ mod test {
         let params = super::Params {
             wallet_name: name.to_string(),
             network: bitcoin::Network::Bitcoin,
+            account: 0,
+            descriptor: vec![],
         };
         let value = super::main(&seed, params).expect("test");
         // assert!(value.contains("xpub"));
@@ -150,6 +246,8 @@ mod test {
         let params = super::Params {
             wallet_name: name.to_string(),
             network: bitcoin::Network::Testnet,
+            account: 0,
+            descriptor: vec![],
         };
         let value = super::main(&seed, params).expect("test");
         println!("{value}");
@@ -162,6 +260,8 @@ mod test {
         let params = super::Params {
             wallet_name: name.to_string(),
             network: bitcoin::Network::Signet,
+            account: 0,
+            descriptor: vec![],
         };
         let value = super::main(&seed, params).expect("test");
         // assert!(!value.contains("xpub"));
@@ -169,4 +269,60 @@ mod test {
         // assert!(value.contains("tpub"));
         assert!(value.contains("signet"));
     }
+
+    #[test]
+    fn test_import_account() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let network = bitcoin::Network::Testnet;
+        let account_0 = super::core_import_json(&seed, network, 0).expect("test");
+        let account_1 = super::core_import_json(&seed, network, 1).expect("test");
+        assert_eq!(account_0.len(), 8);
+        assert_eq!(account_1.len(), 8);
+        assert!(account_0[6].desc.starts_with("tr([01e0b4da/86'/1'/0']"));
+        assert!(account_1[6].desc.starts_with("tr([01e0b4da/86'/1'/1']"));
+    }
+
+    #[test]
+    fn test_import_descriptor() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let secp = Secp256k1::new();
+        let heir: Seed = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".parse().expect("test");
+        let path = super::bip48_path(bitcoin::Network::Testnet, 0, 2);
+        let heir = super::xpub_with_origin(&heir, bitcoin::Network::Testnet, &secp, path);
+        let heir = format!("{heir}/<0;1>/*");
+        let params = |descriptor: &str| super::Params {
+            wallet_name: "inheritance".to_string(),
+            network: bitcoin::Network::Testnet,
+            account: 0,
+            descriptor: vec![descriptor.to_string()],
+        };
+
+        let desc = format!("wsh(or_d(pk(@me),and_v(v:pk({heir}),older(52560))))");
+        let value = super::main(&seed, params(&desc)).expect("test");
+        assert!(value.contains("inheritance"));
+
+        let desc = super::expand_descriptor(&seed, bitcoin::Network::Testnet, &secp, 0, &desc)
+            .expect("test");
+        assert!(desc
+            .to_string()
+            .starts_with("wsh(or_d(pk([01e0b4da/48'/1'/0'/2']tpub"));
+        let import = super::import_descriptors(vec![desc]).expect("test");
+        assert_eq!(import.len(), 2);
+        assert!(import[1].internal);
+
+        // the heir alone is not our descriptor
+        let desc = format!("wsh(pk({heir}))");
+        assert!(super::main(&seed, params(&desc)).is_err());
+
+        let expand =
+            |desc: &str| super::expand_descriptor(&seed, bitcoin::Network::Testnet, &secp, 0, desc);
+        let desc = expand(&format!("sh(wsh(multi(1,@me,{heir})))")).expect("test");
+        assert!(desc
+            .to_string()
+            .starts_with("sh(wsh(multi(1,[01e0b4da/48'/1'/0'/1']tpub"));
+
+        // bip48 keys are not defined for other script types
+        assert!(expand("tr(@me)").is_err());
+        assert!(expand("wpkh(@me)").is_err());
+    }
 }
@@ -0,0 +1,450 @@
+use crate::create::{self, Candidates, TX_OVERHEAD};
+use crate::sign;
+use crate::signed_outpoints::SignedOutpoints;
+use crate::wallet::WalletParams;
+use crate::{error::Error, seed::Seed};
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{absolute, Address, Amount, OutPoint, Sequence, TxOut, Txid, Weight};
+use clap::{ArgGroup, Parser};
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Takes a seed from standard input and creates a bundle of signed transactions sweeping all the
+/// coins of a snapshot of the unspent outputs to an heir, each one valid only after a locktime
+/// staggered by the renewal interval
+///
+/// Spending any of the coins, like when renewing the bundle, invalidates all the transactions
+#[derive(Parser, Debug)]
+#[command(author, version)]
+#[command(group(ArgGroup::new("heir").required(true).args(["heir_address", "heir_descriptor"])))]
+pub struct Params {
+    /// Json file with the unspent outputs, objects with `txid`, `vout`, `scriptPubKey` and
+    /// `amount` in bitcoin like the ones returned by `bitcoin-cli listunspent`
+    pub utxos: PathBuf,
+
+    /// Directory of files with the previous transactions of the unspent outputs in hex, like the
+    /// ones returned by `bitcoin-cli getrawtransaction`. Needed to spend legacy outputs, and
+    /// segwit v0 outputs on mainnet, the ones without it are not swept
+    #[clap(long)]
+    pub prev_txs: Option<PathBuf>,
+
+    /// Address of the heir receiving all the transactions
+    #[clap(long)]
+    pub heir_address: Option<Address<NetworkUnchecked>>,
+
+    /// Descriptor of the heir, the transaction `i` pays to the address at index `i`
+    #[clap(long)]
+    pub heir_descriptor: Option<Descriptor<DescriptorPublicKey>>,
+
+    /// Locktime of the first transaction, a block height if less than 500000000, a unix
+    /// timestamp otherwise
+    #[clap(long)]
+    pub start: u32,
+
+    /// Locktime difference between consecutive transactions, in blocks or in seconds like the
+    /// start
+    #[clap(long)]
+    pub interval: u32,
+
+    /// Number of transactions in the bundle
+    #[clap(long, default_value_t = 4)]
+    pub count: u32,
+
+    /// Fee rate in sat/vB
+    #[clap(long)]
+    pub fee_rate: f64,
+
+    #[command(flatten)]
+    pub wallet: WalletParams,
+
+    /// Directory where the manifest `manifest.json` and every transaction in hex, as
+    /// `<lock_time>.tx`, are also written
+    #[clap(long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// File where an entry for every signed transaction is appended, like `sign --audit-log`
+    #[clap(long)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Json file recording the outpoints spent by the signed transactions, like
+    /// `sign --signed-outpoints`. The coins are recorded as spent by the last transaction
+    #[clap(long)]
+    pub signed_outpoints: Option<PathBuf>,
+
+    /// Sign the bundle even if the coins are recorded as spent by transactions outside the
+    /// bundle, like a previous bundle being renewed
+    #[clap(long)]
+    pub allow_replace: bool,
+}
+
+/// The manifest of the bundle
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// Unspent outputs swept by every transaction
+    pub inputs: Vec<OutPoint>,
+
+    /// Sum of the swept unspent outputs in satoshi
+    pub total: u64,
+
+    /// Unspent outputs in the snapshot not belonging to the descriptors, not swept
+    pub unknown_utxos: Vec<OutPoint>,
+
+    /// Unspent outputs of ours not swept because their previous transaction is missing
+    pub missing_prev_tx: Vec<OutPoint>,
+
+    /// Unspent outputs of ours not swept because spending them costs more than their value at
+    /// the fee rate
+    pub uneconomic_utxos: Vec<OutPoint>,
+
+    /// The transactions ordered by locktime
+    pub transactions: Vec<Presigned>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Presigned {
+    /// The transaction can be included in the blocks following this height, or after this unix
+    /// timestamp as median time past
+    pub lock_time: u32,
+
+    pub lock_type: LockType,
+
+    pub txid: Txid,
+
+    /// Signed transaction in hex
+    pub tx: String,
+
+    /// Heir address
+    pub address: String,
+
+    /// Amount received by the heir in satoshi
+    pub amount: u64,
+
+    /// The absolute fee of the tx in satoshi
+    pub fee: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LockType {
+    /// Block height
+    Height,
+
+    /// Unix timestamp
+    Time,
+}
+
+pub fn main(seed: &Seed, params: Params) -> Result<Output, Error> {
+    let Params {
+        utxos,
+        prev_txs,
+        heir_address,
+        heir_descriptor,
+        start,
+        interval,
+        count,
+        fee_rate,
+        out_dir,
+        audit_log,
+        signed_outpoints,
+        allow_replace,
+        wallet,
+    } = params;
+    let network = wallet.network;
+    if !(fee_rate.is_finite() && fee_rate >= 1.0) {
+        return Err(Error::Other("fee rate must be at least 1 sat/vB"));
+    }
+    let lock_times = lock_times(start, interval, count)?;
+    let lock_type = match absolute::LockTime::from_consensus(start) {
+        absolute::LockTime::Blocks(_) => LockType::Height,
+        absolute::LockTime::Seconds(_) => LockType::Time,
+    };
+
+    let descriptors =
+        create::account_descriptors(seed, network, wallet.accounts, &wallet.descriptor)?;
+    let utxos = create::load_utxos(&utxos)?;
+    let prev_txs = match prev_txs {
+        Some(dir) => create::load_prev_txs(&dir)?,
+        None => HashMap::new(),
+    };
+    let Candidates {
+        candidates,
+        unknown_utxos,
+        missing_prev_tx,
+        uneconomic_utxos,
+    } = create::candidates(
+        &descriptors,
+        wallet.max,
+        network,
+        &utxos,
+        &prev_txs,
+        fee_rate,
+    )?;
+    if candidates.is_empty() {
+        return Err(Error::Other("no unspent outputs of ours worth sweeping"));
+    }
+    let selected: Vec<_> = candidates.iter().collect();
+    let total: u64 = selected.iter().map(|c| c.txout.value.to_sat()).sum();
+    let inputs_weight = selected.iter().map(|c| c.weight).sum::<Weight>();
+
+    let mut psbts = vec![];
+    let mut heirs = vec![];
+    for (i, lock_time) in lock_times.iter().enumerate() {
+        let heir = match (heir_address.as_ref(), heir_descriptor.as_ref()) {
+            (Some(address), _) => address.clone().require_network(network)?,
+            (None, Some(desc)) => desc.at_derivation_index(i as u32)?.address(network)?,
+            (None, None) => unreachable!("required by clap"),
+        };
+        let script_pubkey = heir.script_pubkey();
+        let output_weight = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }
+        .weight();
+        let fee = create::fee(TX_OVERHEAD + inputs_weight + output_weight, fee_rate);
+        let amount = total
+            .checked_sub(fee)
+            .filter(|a| *a >= script_pubkey.minimal_non_dust().to_sat())
+            .ok_or(Error::Other("swept amount is dust after fees"))?;
+
+        let output = TxOut {
+            value: Amount::from_sat(amount),
+            script_pubkey,
+        };
+        let psbt = create::unsigned_psbt(
+            &selected,
+            vec![output],
+            absolute::LockTime::from_consensus(*lock_time),
+            Sequence::ENABLE_LOCKTIME_NO_RBF,
+        )?;
+        psbts.push((PathBuf::from(lock_time.to_string()), psbt));
+        heirs.push(heir);
+    }
+
+    // the transactions of the bundle spend the same coins, conflicts are only the ones outside
+    if let Some(path) = signed_outpoints.as_ref() {
+        let record = SignedOutpoints::load(path)?;
+        let bundle: Vec<_> = psbts
+            .iter()
+            .map(|(_, p)| p.unsigned_tx.compute_txid())
+            .collect();
+        let mut txids: Vec<_> = psbts
+            .iter()
+            .flat_map(|(_, p)| record.conflicts(&p.unsigned_tx))
+            .filter(|txid| !bundle.contains(txid))
+            .map(|txid| txid.to_string())
+            .collect();
+        txids.sort();
+        txids.dedup();
+        if !txids.is_empty() && !allow_replace {
+            return Err(format!(
+                "coins already signed in the transactions with unsigned txid {}, use --allow-replace to sign a new bundle",
+                txids.join(", ")
+            )
+            .into());
+        }
+    }
+
+    let params = sign::Params {
+        psbts: vec![],
+        wallet,
+        require_prev_tx: None,
+        max_fee: None,
+        max_fee_rate: None,
+        max_fee_percent: None,
+        allow_any_sighash: false,
+        policy: None,
+        audit_log,
+        signed_outpoints,
+        allow_replace: true,
+        text: false,
+        sign_only: false,
+        binary_dir: None,
+    };
+    let signed = sign::sign_psbts(seed, params, psbts)?;
+
+    let mut transactions = vec![];
+    for ((signed, heir), lock_time) in signed.into_iter().zip(heirs).zip(lock_times) {
+        let tx = signed
+            .tx()
+            .ok_or(Error::Other("some inputs of the bundle can't be finalized"))?;
+        transactions.push(Presigned {
+            lock_time,
+            lock_type,
+            txid: tx.compute_txid(),
+            tx: serialize_hex(&tx),
+            address: heir.to_string(),
+            amount: tx.output[0].value.to_sat(),
+            fee: signed.fee,
+        });
+    }
+
+    let output = Output {
+        inputs: selected.iter().map(|c| c.outpoint).collect(),
+        total,
+        unknown_utxos,
+        missing_prev_tx,
+        uneconomic_utxos,
+        transactions,
+    };
+    if let Some(out_dir) = out_dir.as_ref() {
+        for presigned in output.transactions.iter() {
+            let path = out_dir.join(format!("{}.tx", presigned.lock_time));
+            std::fs::write(path, &presigned.tx)?;
+        }
+        let manifest =
+            serde_json::to_string_pretty(&output).expect("doesn't contain non-string key");
+        std::fs::write(out_dir.join("manifest.json"), manifest)?;
+    }
+    Ok(output)
+}
+
+/// The staggered locktimes, all of the same type of `start`
+fn lock_times(start: u32, interval: u32, count: u32) -> Result<Vec<u32>, Error> {
+    if count == 0 || interval == 0 {
+        return Err(Error::Other("count and interval must be positive"));
+    }
+    let last = interval
+        .checked_mul(count - 1)
+        .and_then(|d| d.checked_add(start))
+        .ok_or(Error::Other("locktime overflow"))?;
+    let is_height = |l| absolute::LockTime::from_consensus(l).is_block_height();
+    if start == 0 {
+        return Err(Error::Other("start locktime must be positive"));
+    }
+    if is_height(start) != is_height(last) {
+        return Err(Error::Other(
+            "locktimes must be all block heights or all timestamps",
+        ));
+    }
+    Ok((0..count).map(|i| start + i * interval).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lock_times, LockType, Params};
+    use crate::{verify_log, Seed};
+    use bitcoin::consensus::encode::deserialize_hex;
+    use bitcoin::{Network, Transaction};
+    use miniscript::{Descriptor, DescriptorPublicKey};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
+    const DESCRIPTOR_TESTNET: &str = include_str!("../../wallet/descriptor_testnet");
+
+    #[test]
+    fn test_lock_times() {
+        assert_eq!(lock_times(100, 10, 3).expect("test"), vec![100, 110, 120]);
+        assert!(lock_times(0, 10, 3).is_err());
+        assert!(lock_times(100, 0, 3).is_err());
+        assert!(lock_times(100, 10, 0).is_err());
+        assert!(lock_times(499_999_990, 10, 3).is_err());
+        assert!(lock_times(u32::MAX - 10, 10, 3).is_err());
+    }
+
+    #[test]
+    fn test_inheritance_bundle() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let single = desc.into_single_descriptors().expect("test");
+        let utxo = |index, vout, amount| {
+            let script_pubkey = single[0]
+                .at_derivation_index(index)
+                .expect("test")
+                .script_pubkey();
+            format!(
+                r#"{{"txid": "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754", "vout": {vout}, "scriptPubKey": "{script_pubkey:x}", "amount": {amount}}}"#
+            )
+        };
+        let snapshot = format!(
+            "[{}, {}, {}]",
+            utxo(0, 0, "0.001"),
+            utxo(3, 1, "0.0005"),
+            utxo(5, 2, "0.000001"),
+        );
+        let mut f = NamedTempFile::new().expect("test");
+        f.as_file_mut()
+            .write_all(snapshot.as_bytes())
+            .expect("Unable to write data");
+        let heir_descriptor: Descriptor<DescriptorPublicKey> = "wpkh([73c5da0a/84'/1'/0']tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M/0/*)".parse().expect("test");
+        let dir = tempfile::tempdir().expect("test");
+        let records = tempfile::tempdir().expect("test");
+        let log = records.path().join("audit.log");
+        let params = |heir_address: Option<&str>| Params {
+            utxos: f.path().to_path_buf(),
+            prev_txs: None,
+            heir_address: heir_address.map(|a| a.parse().expect("test")),
+            heir_descriptor: heir_address.is_none().then(|| heir_descriptor.clone()),
+            start: 900_000,
+            interval: 4_320,
+            count: 3,
+            fee_rate: 2.0,
+            wallet: crate::wallet::WalletParams {
+                network,
+                max: 10,
+                accounts: 1,
+                descriptor: vec![],
+            },
+            out_dir: Some(dir.path().to_path_buf()),
+            audit_log: Some(log.clone()),
+            signed_outpoints: Some(records.path().join("signed_outpoints.json")),
+            allow_replace: false,
+        };
+
+        let heir = "tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c";
+        let output = super::main(&seed, params(Some(heir))).expect("test");
+        assert_eq!(output.inputs.len(), 2);
+        assert_eq!(output.total, 150_000);
+        assert_eq!(output.uneconomic_utxos.len(), 1);
+        assert_eq!(output.uneconomic_utxos[0].vout, 2);
+        assert_eq!(output.transactions.len(), 3);
+        for (i, presigned) in output.transactions.iter().enumerate() {
+            assert_eq!(presigned.lock_time, 900_000 + i as u32 * 4_320);
+            assert_eq!(presigned.lock_type, LockType::Height);
+            assert_eq!(presigned.address, heir);
+            assert_eq!(presigned.amount + presigned.fee, 150_000);
+
+            let tx: Transaction = deserialize_hex(&presigned.tx).expect("test");
+            assert_eq!(tx.compute_txid(), presigned.txid);
+            assert_eq!(tx.lock_time.to_consensus_u32(), presigned.lock_time);
+            assert!(tx.is_lock_time_enabled());
+            assert!(tx.input.iter().all(|i| i.witness.len() == 1));
+            assert!(presigned.fee as f64 / tx.vsize() as f64 >= 2.0);
+
+            let path = dir.path().join(format!("{}.tx", presigned.lock_time));
+            assert_eq!(std::fs::read_to_string(path).expect("test"), presigned.tx);
+        }
+        let manifest = std::fs::read_to_string(dir.path().join("manifest.json")).expect("test");
+        let manifest: super::Output = serde_json::from_str(&manifest).expect("test");
+        assert_eq!(manifest.transactions[2].txid, output.transactions[2].txid);
+
+        // every transaction is logged, signing the same bundle again is fine
+        assert!(super::main(&seed, params(Some(heir))).is_ok());
+        let verify = |log: &std::path::Path| {
+            let params = verify_log::Params {
+                log: log.to_path_buf(),
+                head: None,
+            };
+            verify_log::main(params).expect("test").entries
+        };
+        assert_eq!(verify(&log), 6);
+
+        // a different bundle is a replacement
+        assert!(super::main(&seed, params(None)).is_err());
+        let params = Params {
+            allow_replace: true,
+            ..params(None)
+        };
+
+        // a different heir address for every transaction
+        let output = super::main(&seed, params).expect("test");
+        let addresses: Vec<_> = output.transactions.iter().map(|t| &t.address).collect();
+        assert_ne!(addresses[0], addresses[1]);
+        assert_ne!(addresses[1], addresses[2]);
+        assert_eq!(verify(&log), 9);
+    }
+}
@@ -8,10 +8,20 @@ pub use serde_json;
 
 mod error;
 mod seed;
+mod signed_outpoints;
 
+pub mod addresses;
+pub mod check_presigned;
+pub mod create;
+pub mod derive;
 pub mod import;
+pub mod inheritance;
+pub mod policy;
 pub mod sign;
 pub mod spendable;
+pub mod split;
+pub mod verify_log;
+pub mod wallet;
 
 /// Read standard input as string, trimming new lines
 pub fn read_stdin() -> Result<String, Error> {
@@ -23,12 +33,23 @@ pub fn read_stdin() -> Result<String, Error> {
     Ok(s.chars().filter(|c| *c != '\n').collect())
 }
 
-/// Read standard input as string, trimming new lines
-pub fn read_stdin_seed() -> Result<Seed, Error> {
-    let s = read_stdin()?;
-    Ok(s.parse()?)
+/// Read standard input as lines, the trailing carriage return is removed
+pub fn read_stdin_lines() -> Result<Vec<String>, Error> {
+    let mut stdin = std::io::stdin().lock();
+    let mut buffer = vec![];
+    stdin.read_to_end(&mut buffer)?;
+    let s = std::str::from_utf8(&buffer)?;
+
+    Ok(s.lines()
+        .map(|l| l.trim_end_matches('\r').to_string())
+        .collect())
 }
 
-fn debug_to_string<D: std::fmt::Debug>(d: D) -> String {
-    format!("{d:?}")
+/// Read the seed from standard input, see [`Seed::from_lines`] for the format.
+///
+/// The bip39 passphrase can be provided after the seed without being persisted, eg:
+/// `(cat MNEMONIC.age | age -d; read -s P; echo "$P") | sign psbt_file`
+pub fn read_stdin_seed() -> Result<Seed, Error> {
+    let lines = read_stdin_lines()?;
+    Ok(Seed::from_lines(&lines)?)
 }
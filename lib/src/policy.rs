@@ -0,0 +1,168 @@
+use crate::sign::{OutputDetail, Ownership};
+use crate::spendable::precompute_addresses;
+use crate::Error;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Network, ScriptBuf};
+use miniscript::{Descriptor, DescriptorPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Spending policy enforced by `sign` on every PSBT, loaded from a json file like:
+///
+/// ```json
+/// {
+///   "allowed_addresses": ["bc1q..."],
+///   "allowed_descriptors": ["wpkh([d34db33f/84'/0'/0']xpub.../0/*)"],
+///   "max_sent": 10000000,
+///   "max_fee": 50000,
+///   "allowed_lock_times": [{ "min": 0, "max": 0 }, { "min": 850000, "max": 900000 }]
+/// }
+/// ```
+///
+/// Every field is optional, missing or empty fields don't restrict anything.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Addresses the foreign outputs can pay to
+    #[serde(default)]
+    pub allowed_addresses: Vec<Address<NetworkUnchecked>>,
+
+    /// Descriptors the foreign outputs can pay to, checked up to the `max` index given to `sign`
+    #[serde(default)]
+    pub allowed_descriptors: Vec<String>,
+
+    /// Maximum amount in satoshi sent to foreign outputs by a single transaction
+    pub max_sent: Option<u64>,
+
+    /// Maximum fee in satoshi of a single transaction
+    pub max_fee: Option<u64>,
+
+    /// Inclusive ranges of the allowed transaction locktime
+    #[serde(default)]
+    pub allowed_lock_times: Vec<LockTimeRange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockTimeRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// A [`Policy`] with the allowed destinations converted to script pubkeys
+pub(crate) struct CheckedPolicy {
+    policy: Policy,
+    allowed_script_pubkeys: HashSet<ScriptBuf>,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Policy, Error> {
+        let file = std::fs::File::open(path)?;
+        serde_json::from_reader(file)
+            .map_err(|e| Error::Policy(format!("invalid policy file {}: {e}", path.display())))
+    }
+
+    pub(crate) fn prepare(self, network: Network, max: u32) -> Result<CheckedPolicy, Error> {
+        let mut allowed_script_pubkeys = HashSet::new();
+        for address in self.allowed_addresses.iter() {
+            let address = address.clone().require_network(network)?;
+            allowed_script_pubkeys.insert(address.script_pubkey());
+        }
+        let mut descriptors = vec![];
+        for desc in self.allowed_descriptors.iter() {
+            let desc: Descriptor<DescriptorPublicKey> = desc.parse()?;
+            descriptors.extend(desc.into_single_descriptors()?);
+        }
+        let addresses = precompute_addresses(&descriptors, max, network)?;
+        allowed_script_pubkeys.extend(addresses.into_keys().map(|a| a.script_pubkey()));
+        Ok(CheckedPolicy {
+            policy: self,
+            allowed_script_pubkeys,
+        })
+    }
+}
+
+impl CheckedPolicy {
+    /// Check the outputs, the fee and the locktime of a transaction, the error explains the
+    /// violated rule
+    pub(crate) fn check(
+        &self,
+        outputs: &[OutputDetail],
+        fee: u64,
+        lock_time: u32,
+    ) -> Result<(), Error> {
+        let policy = &self.policy;
+        let foreign: Vec<_> = outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.ownership != Ownership::Ours)
+            .collect();
+
+        let restricted =
+            !policy.allowed_addresses.is_empty() || !policy.allowed_descriptors.is_empty();
+        for (output_idx, output) in foreign.iter() {
+            // data outputs like OP_RETURN without value don't send anything
+            let data = output.amount == 0 && output.address.is_none();
+            if restricted && !data && !self.allowed_script_pubkeys.contains(&output.script_pubkey) {
+                let destination = output
+                    .address
+                    .clone()
+                    .unwrap_or_else(|| format!("{:x}", output.script_pubkey));
+                return Err(Error::Policy(format!(
+                    "output #{output_idx} pays to {destination} which is not allowed"
+                )));
+            }
+        }
+
+        let sent: u64 = foreign.iter().map(|(_, o)| o.amount).sum();
+        if let Some(max_sent) = policy.max_sent {
+            if sent > max_sent {
+                return Err(Error::Policy(format!(
+                    "sent amount {sent} is higher than the maximum {max_sent}"
+                )));
+            }
+        }
+        if let Some(max_fee) = policy.max_fee {
+            if fee > max_fee {
+                return Err(Error::Policy(format!(
+                    "fee {fee} is higher than the maximum {max_fee}"
+                )));
+            }
+        }
+        let lock_time_allowed = policy.allowed_lock_times.is_empty()
+            || policy
+                .allowed_lock_times
+                .iter()
+                .any(|r| (r.min..=r.max).contains(&lock_time));
+        if !lock_time_allowed {
+            return Err(Error::Policy(format!(
+                "locktime {lock_time} is not in the allowed ranges"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Policy;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy: Policy = serde_json::from_str("{}").expect("test");
+        assert!(policy.allowed_addresses.is_empty());
+        assert!(policy.max_sent.is_none());
+
+        let policy: Policy = serde_json::from_str(
+            r#"{"allowed_addresses": ["bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"], "max_fee": 1000, "allowed_lock_times": [{"min": 0, "max": 0}]}"#,
+        )
+        .expect("test");
+        assert_eq!(policy.allowed_addresses.len(), 1);
+        assert_eq!(policy.max_fee, Some(1000));
+        assert_eq!(policy.allowed_lock_times[0].max, 0);
+
+        // typos are not silently ignored
+        assert!(serde_json::from_str::<Policy>(r#"{"max_fees": 1000}"#).is_err());
+    }
+}
@@ -3,10 +3,14 @@ use std::str::FromStr;
 use bip39::Mnemonic;
 use bitcoin::{
     bip32::{Fingerprint, Xpriv},
+    hashes::{hmac, sha512, Hash, HashEngine},
     secp256k1::{All, Secp256k1},
     Network,
 };
-use codex32::Codex32String;
+use codex32::{Codex32String, Fe};
+
+/// Share indexes in the order used by bip93, the secret index `s` is excluded
+const SHARE_INDEXES: &str = "acdefghjklmnpqrtuvwxyz023456789";
 
 #[derive(Debug)]
 pub enum SeedError {
@@ -15,18 +19,37 @@ pub enum SeedError {
     Codex32(codex32::Error),
 
     NeitherMnemonicNorCodex32(String),
+
+    /// Threshold must be between 2 and 9, number of shares between threshold and 31
+    InvalidSplit {
+        threshold: usize,
+        shares: usize,
+    },
+
+    MissingSeed,
+
+    /// The passphrase line is only whitespace or starts or ends with whitespace, likely a
+    /// mistake like a stray line, or it's followed by other lines
+    InvalidPassphrase,
+}
+
+impl From<codex32::Error> for SeedError {
+    fn from(e: codex32::Error) -> Self {
+        SeedError::Codex32(e)
+    }
 }
 
+/// The secret and the bip39 passphrase (the "25th word"), empty if not used
 pub enum Seed {
-    Mnemonic(Mnemonic),
-    Codex32(Codex32String),
+    Mnemonic(Mnemonic, String),
+    Codex32(Codex32String, String),
 }
 
 impl std::fmt::Display for Seed {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Seed::Mnemonic(e) => write!(f, "{}", e),
-            Seed::Codex32(e) => write!(f, "{}", e),
+            Seed::Mnemonic(e, _) => write!(f, "{}", e),
+            Seed::Codex32(e, _) => write!(f, "{}", e),
         }
     }
 }
@@ -34,15 +57,117 @@ impl std::fmt::Display for Seed {
 impl Seed {
     pub fn mnemonic(&self) -> Mnemonic {
         match self {
-            Seed::Mnemonic(e) => e.clone(),
-            Seed::Codex32(e) => {
+            Seed::Mnemonic(e, _) => e.clone(),
+            Seed::Codex32(e, _) => {
                 Mnemonic::from_entropy(&e.parts().data()).expect("guaranteed 32 bytes")
             }
         }
     }
+
+    pub fn passphrase(&self) -> &str {
+        match self {
+            Seed::Mnemonic(_, p) => p,
+            Seed::Codex32(_, p) => p,
+        }
+    }
+
+    /// Returns the same seed using the given bip39 passphrase
+    pub fn with_passphrase(self, passphrase: &str) -> Self {
+        match self {
+            Seed::Mnemonic(e, _) => Seed::Mnemonic(e, passphrase.to_string()),
+            Seed::Codex32(e, _) => Seed::Codex32(e, passphrase.to_string()),
+        }
+    }
+
+    /// Parse the seed from lines of text.
+    ///
+    /// The first line is the seed (bip39 or bip93). If it's a codex32 share of a k-of-n split
+    /// instead of the secret, the first k lines are the shares to recover the secret from.
+    /// The optional following line is the bip39 passphrase, it must be the last line and can't
+    /// start or end with whitespace, so that a stray line isn't silently used as a passphrase
+    /// giving a different wallet. An empty line is the same as no passphrase.
+    pub fn from_lines(lines: &[String]) -> Result<Self, SeedError> {
+        let first = lines.first().ok_or(SeedError::MissingSeed)?;
+        let (seed, consumed) = match Codex32String::from_string(first.to_string())
+            .ok()
+            .and_then(|c| share_threshold(&c))
+        {
+            Some(threshold) => {
+                let shares = lines
+                    .iter()
+                    .take(threshold)
+                    .map(|l| Codex32String::from_string(l.to_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                (Seed::from_shares(&shares)?, threshold)
+            }
+            None => (first.parse()?, 1),
+        };
+        Ok(match &lines[consumed..] {
+            [] => seed,
+            [passphrase] if passphrase.trim() == passphrase => seed.with_passphrase(passphrase),
+            _ => return Err(SeedError::InvalidPassphrase),
+        })
+    }
+
+    /// Recover the codex32 secret from the shares of a k-of-n split, at least k are needed
+    pub fn from_shares(shares: &[Codex32String]) -> Result<Self, SeedError> {
+        let secret = Codex32String::interpolate_at(shares, Fe::S)?;
+        Ok(Seed::Codex32(secret, String::new()))
+    }
+
+    /// Split the seed in `shares` codex32 strings, any `threshold` of them recover the secret.
+    ///
+    /// The first `threshold - 1` shares are derived from the secret, the `id` and the `threshold`
+    /// with HMAC-SHA512, so that the same params give the same shares and no source of randomness
+    /// is needed on the offline machine. This deviates from bip93, which generates them randomly,
+    /// but they are still indistinguishable from random without the secret. The others are
+    /// interpolated as specified in bip93. The passphrase is not part of the shares.
+    pub fn split(
+        &self,
+        id: &str,
+        threshold: usize,
+        shares: usize,
+    ) -> Result<Vec<Codex32String>, SeedError> {
+        if !(2..=9).contains(&threshold) || shares < threshold || shares > SHARE_INDEXES.len() {
+            return Err(SeedError::InvalidSplit { threshold, shares });
+        }
+        let data = self.mnemonic().to_entropy();
+        let id = id.to_lowercase();
+        let indexes = SHARE_INDEXES
+            .chars()
+            .map(Fe::from_char)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut initial = vec![Codex32String::from_seed(
+            "ms",
+            threshold,
+            &id,
+            Fe::S,
+            &data,
+        )?];
+        for index in &indexes[..threshold - 1] {
+            let mut engine = hmac::HmacEngine::<sha512::Hash>::new(&data);
+            engine.input(format!("{threshold}{id}{}", index.to_char()).as_bytes());
+            let random = hmac::Hmac::<sha512::Hash>::from_engine(engine).to_byte_array();
+            initial.push(Codex32String::from_seed(
+                "ms",
+                threshold,
+                &id,
+                *index,
+                &random[..data.len()],
+            )?);
+        }
+
+        let mut result = initial[1..].to_vec();
+        for index in &indexes[threshold - 1..shares] {
+            result.push(Codex32String::interpolate_at(&initial, *index)?);
+        }
+        Ok(result)
+    }
+
     pub fn xprv(&self, network: Network) -> Xpriv {
         let mnemonic = self.mnemonic();
-        Xpriv::new_master(network, &mnemonic.to_seed("")).expect("Xpriv fails")
+        Xpriv::new_master(network, &mnemonic.to_seed(self.passphrase())).expect("Xpriv fails")
     }
 
     pub fn fingerprint(&self, secp: &Secp256k1<All>) -> Fingerprint {
@@ -55,21 +180,31 @@ impl FromStr for Seed {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if let Ok(mnemonic) = s.parse::<Mnemonic>() {
-            Ok(Seed::Mnemonic(mnemonic))
+            Ok(Seed::Mnemonic(mnemonic, String::new()))
         } else if let Ok(codex32) = Codex32String::from_string(s.to_string()) {
-            Ok(Seed::Codex32(codex32))
+            Ok(Seed::Codex32(codex32, String::new()))
         } else {
             Err(SeedError::NeitherMnemonicNorCodex32(s.to_string()))
         }
     }
 }
 
+/// Returns the threshold if the given codex32 string is a share and not the secret
+fn share_threshold(codex32: &Codex32String) -> Option<usize> {
+    let s = codex32.to_string().to_lowercase();
+    let (_, data) = s.rsplit_once('1')?;
+    let mut chars = data.chars();
+    let threshold = chars.next()?.to_digit(10)? as usize;
+    let index = chars.nth(4)?;
+    (threshold > 1 && index != 's').then_some(threshold)
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
 
     use bip39::Mnemonic;
-    use bitcoin::{bip32::Xpriv, secp256k1::Secp256k1, Network};
+    use bitcoin::{bip32::Xpriv, hex::FromHex, secp256k1::Secp256k1, Network};
     use codex32::Codex32String;
 
     use super::Seed;
@@ -122,4 +257,88 @@ mod test {
             assert_eq!(b39.fingerprint(&secp), b93.fingerprint(&secp));
         }
     }
+
+    #[test]
+    fn test_passphrase() {
+        let secp = Secp256k1::new();
+
+        // bip39 test vector with passphrase "TREZOR"
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = Seed::from_str(mnemonic).expect("test");
+        let without = seed.fingerprint(&secp);
+
+        let seed = seed.with_passphrase("TREZOR");
+        assert_eq!(seed.xprv(Network::Bitcoin).to_string(), "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF");
+        assert_ne!(without, seed.fingerprint(&secp));
+
+        let b39 = Seed::from_str(MNEMONIC)
+            .expect("test")
+            .with_passphrase("TREZOR");
+        let b93 = Seed::from_str(CODEX_32)
+            .expect("test")
+            .with_passphrase("TREZOR");
+        assert_eq!(b39.fingerprint(&secp), b93.fingerprint(&secp));
+    }
+
+    #[test]
+    fn test_recover_bip93_vector() {
+        // bip93 test vector 2
+        let lines = vec![
+            "MS12NAMEA320ZYXWVUTSRQPNMLKJHGFEDCAXRPP870HKKQRM".to_string(),
+            "MS12NAMECACDEFGHJKLMNPQRSTUVWXYZ023FTR2GDZMPY6PN".to_string(),
+        ];
+        let seed = Seed::from_lines(&lines).expect("test");
+        assert_eq!(
+            seed.to_string(),
+            "MS12NAMES6XQGUZTTXKEQNJSJZV4JV3NZ5K3KWGSPHUH6EVW"
+        );
+        assert_eq!(
+            seed.mnemonic().to_entropy(),
+            Vec::<u8>::from_hex("d1808e096b35b209ca12132b264662a5").expect("test")
+        );
+        assert_eq!(seed.passphrase(), "");
+
+        let mut lines = lines;
+        lines.push("TREZOR".to_string());
+        let seed = Seed::from_lines(&lines).expect("test");
+        assert_eq!(seed.passphrase(), "TREZOR");
+
+        for invalid in [" ", "TREZOR ", " TREZOR", "\tTREZOR"] {
+            lines[2] = invalid.to_string();
+            assert!(Seed::from_lines(&lines).is_err(), "{invalid:?}");
+        }
+        lines[2] = String::new();
+        assert_eq!(Seed::from_lines(&lines).expect("test").passphrase(), "");
+        lines.push("TREZOR".to_string());
+        assert!(Seed::from_lines(&lines).is_err());
+        lines.truncate(2);
+
+        assert!(Seed::from_lines(&lines[..1]).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        let secp = Secp256k1::new();
+        let seed = Seed::from_str(MNEMONIC).expect("test");
+        let shares = seed.split("leet", 2, 3).expect("test");
+        assert_eq!(shares.len(), 3);
+        assert_eq!(shares, seed.split("leet", 2, 3).expect("test"));
+        assert!(shares[0].to_string().starts_with("ms12leeta"));
+        assert!(shares[1].to_string().starts_with("ms12leetc"));
+        assert!(shares[2].to_string().starts_with("ms12leetd"));
+
+        for (a, b) in [(0, 1), (0, 2), (2, 1)] {
+            let lines = vec![shares[a].to_string(), shares[b].to_string()];
+            let recovered = Seed::from_lines(&lines).expect("test");
+            assert_eq!(recovered.fingerprint(&secp), seed.fingerprint(&secp));
+        }
+
+        let lines = vec![shares[0].to_string()];
+        assert!(Seed::from_lines(&lines).is_err());
+
+        assert!(seed.split("leet", 1, 3).is_err());
+        assert!(seed.split("leet", 3, 2).is_err());
+        assert!(seed.split("leet", 2, 32).is_err());
+        assert_eq!(seed.split("leet", 9, 31).expect("test").len(), 31);
+    }
 }
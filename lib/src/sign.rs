@@ -1,20 +1,32 @@
-use crate::debug_to_string;
+use crate::policy::Policy;
+use crate::signed_outpoints::SignedOutpoints;
 use crate::spendable::{compute_finite_descriptors, precompute_addresses};
+use crate::verify_log;
+use crate::wallet::WalletParams;
 use crate::{error::Error, seed::Seed};
 use std::collections::HashMap;
 
-use bitcoin::bip32::{DerivationPath, Fingerprint};
-use bitcoin::hex::FromHex;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource, Xpriv, Xpub};
+use bitcoin::hex::{DisplayHex, FromHex};
 use bitcoin::psbt::SigningKeys;
 
-use bitcoin::script::PushBytes;
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::All;
 use bitcoin::{
-    consensus::{encode::serialize_hex, Decodable},
+    consensus::{
+        encode::{serialize_hex, VarInt},
+        Decodable,
+    },
     key::Secp256k1,
-    Network, Psbt, Transaction, Txid, Witness,
+    relative, EcdsaSighashType, Network, OutPoint, Psbt, TapSighashType, Transaction, Txid, Weight,
+};
+use bitcoin::{
+    Address, CompressedPublicKey, Script, ScriptBuf, TapLeafHash, TapNodeHash, XOnlyPublicKey,
 };
-use bitcoin::{script, Address, TapLeafHash};
 use clap::Parser;
+use miniscript::descriptor::DescriptorType;
+use miniscript::psbt::{self, InputError, PsbtExt};
+use miniscript::{Descriptor, DescriptorPublicKey, Legacy, Miniscript, Segwitv0, Tap};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::io::Read;
@@ -24,7 +36,7 @@ use std::str::FromStr;
 pub type TapKeyOrigin =
     BTreeMap<bitcoin::XOnlyPublicKey, (Vec<TapLeafHash>, (Fingerprint, DerivationPath))>;
 
-/// Takes a seed (bip39 or bip93) from standard input and 1+ PSBT. Computes the standard descriptors an try to sign PSBTs with details.
+/// Takes a seed (bip39 or bip93) from standard input and 1+ PSBT. Computes the standard descriptors, or uses the given ones, an try to sign PSBTs with details.
 #[derive(Parser, Debug)]
 #[command(author, version)]
 pub struct Params {
@@ -32,69 +44,219 @@ pub struct Params {
     #[clap(name = "psbt")]
     pub psbts: Vec<PathBuf>,
 
-    /// Bitcoin Network. bitcoin,testnet,signet are possible values
-    #[clap(short, long, env)]
-    pub network: Network,
-
-    /// Generated addresses up to this number, generated addresses are used to check if the outputs are mine and compute the net balance.
-    #[clap(short, long, default_value_t = 1000)]
-    pub max: u32,
+    #[command(flatten)]
+    pub wallet: WalletParams,
+
+    /// Require the previous transaction (`non_witness_utxo`) for segwit v0 and legacy inputs, so
+    /// that their amounts can't be faked. Default to true for mainnet, false otherwise
+    #[clap(long)]
+    pub require_prev_tx: Option<bool>,
+
+    /// Refuse to sign if the fee is higher than this amount of satoshi
+    #[clap(long)]
+    pub max_fee: Option<u64>,
+
+    /// Refuse to sign if the fee rate in sat/vB is higher than this. Before signing the weight is
+    /// estimated with the final witness of finalized inputs, the maximum satisfaction weight of
+    /// our descriptors or of the scripts in the PSBT for the others, and the worst case of single
+    /// key inputs of other participants. Inputs with unknown satisfaction are counted without it
+    /// and reported in `unestimated_inputs`
+    #[clap(long)]
+    pub max_fee_rate: Option<f64>,
+
+    /// Refuse to sign if the fee is higher than this percentage of the amount sent to others
+    #[clap(long)]
+    pub max_fee_percent: Option<f64>,
+
+    /// Sign inputs requesting a sighash type different from ALL or DEFAULT, like
+    /// SINGLE|ANYONECANPAY, which allows others to change the transaction after signing
+    #[clap(long)]
+    pub allow_any_sighash: bool,
+
+    /// Json file with the spending policy every PSBT must respect: allowed destinations, maximum
+    /// amount sent, maximum fee and allowed locktimes
+    #[clap(long)]
+    pub policy: Option<PathBuf>,
+
+    /// File where an entry for every signed PSBT is appended, each entry contains the hash of the
+    /// previous one and the log can be checked with `verify-log`
+    #[clap(long)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Json file recording the outpoints spent by the signed transactions, PSBTs spending a
+    /// recorded outpoint in a different transaction are refused
+    #[clap(long)]
+    pub signed_outpoints: Option<PathBuf>,
+
+    /// Sign PSBTs conflicting with the recorded outpoints, like replacements with a higher fee
+    #[clap(long)]
+    pub allow_replace: bool,
+
+    /// Print a human readable text instead of json
+    #[clap(long)]
+    pub text: bool,
+
+    /// Only add signatures, without finalizing the inputs and extracting the transaction, so that
+    /// the PSBT can be combined with other signers' work
+    #[clap(long)]
+    pub sign_only: bool,
+
+    /// Directory where the resulting PSBTs are also written in binary format, as `<psbt file stem>.signed.psbt`
+    #[clap(long)]
+    pub binary_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Output {
-    /// Transaction in hex
-    pub tx: String,
+    /// Transaction in hex, missing if some inputs are not finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx: Option<String>,
 
     /// PSBT in base64
     pub psbt: String,
 
-    /// Transaction hash
-    pub txid: Txid,
+    /// Transaction hash, missing if some inputs are not finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub txid: Option<Txid>,
+
+    /// Inputs of the transaction
+    pub inputs: Vec<InputDetail>,
 
-    /// human readable inputs
-    pub inputs: Vec<String>,
+    /// Outputs of the transaction
+    pub outputs: Vec<OutputDetail>,
 
-    /// human readable outputs
-    pub outputs: Vec<String>,
+    /// Locktime of the transaction, a block height if less than 500000000, a unix timestamp
+    /// otherwise
+    pub lock_time: u32,
 
     /// Signatures added to the PSBT
     pub signatures_added: usize,
 
-    /// The absolute fee of the tx
-    pub fee: String,
+    /// Indexes of the inputs we added signatures to
+    pub signed_inputs: Vec<usize>,
+
+    /// Indexes of the inputs we didn't sign, like the ones of other participants in a collaborative transaction
+    pub unsigned_inputs: Vec<usize>,
+
+    /// Indexes of the outputs with key origins of our seed not reproducing the script pubkey,
+    /// possibly a fake change
+    pub fake_outputs: Vec<usize>,
+
+    /// The absolute fee of the tx in satoshi
+    pub fee: u64,
+
+    /// Weight of the transaction, missing if some inputs are not finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u64>,
 
-    /// The net balance from the perspective of the given wallet descriptor
-    pub bal: String,
+    /// Virtual size of the transaction, missing if some inputs are not finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vsize: Option<u64>,
+
+    /// Fee rate in sat/vB, missing if some inputs are not finalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee_rate: Option<f64>,
+
+    /// Fee rate in sat/vB estimated before signing when `--max-fee-rate` is given, with the
+    /// maximum satisfaction weight of the inputs not finalized, so that the final fee rate is not
+    /// lower unless some inputs are in `unestimated_inputs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_fee_rate: Option<f64>,
+
+    /// Indexes of the inputs whose satisfaction weight is unknown, like inputs of other
+    /// participants without their scripts in the PSBT. They are counted in `estimated_fee_rate`
+    /// without satisfaction, so the estimated weight is only a lower bound
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unestimated_inputs: Vec<usize>,
+
+    /// The net balance in satoshi from the perspective of the standard descriptors or of the given ones
+    pub bal: i64,
+
+    /// Unsigned txids of previously signed transactions spending some of the same outpoints, this
+    /// transaction has been signed because of `--allow-replace`. They are the final txids unless
+    /// the transactions have legacy inputs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaced_txids: Vec<Txid>,
 }
 
-pub fn main(seed: &Seed, params: Params) -> Result<Vec<Output>, Error> {
-    let Params {
-        psbts,
-        network,
-        max,
-    } = params;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputDetail {
+    /// The previous output spent
+    pub outpoint: OutPoint,
 
-    let secp = Secp256k1::new();
+    /// Amount in satoshi
+    pub amount: u64,
 
-    let descriptors = compute_finite_descriptors(seed, network, &secp)?;
-    let addresses = precompute_addresses(&descriptors, max, network)?;
-    let script_pubkeys: HashMap<_, _> = addresses
-        .into_iter()
-        .map(|(addr, t)| (addr.script_pubkey(), t))
-        .collect();
+    /// Address of the previous output, missing for scripts without address like bare scripts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
 
-    let xpriv = seed.xprv(network);
+    /// Script pubkey of the previous output in hex
+    pub script_pubkey: ScriptBuf,
 
-    let mut results = vec![];
-    let mut data = Vec::new();
+    /// Type of the script pubkey, like `p2wpkh`, `p2tr`, `p2a` or `nonstandard`
+    pub script_type: String,
+
+    pub ownership: Ownership,
+
+    /// Descriptor type, like `Wpkh` or `Tr`, when the input is ours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc_type: Option<String>,
+
+    /// Derivation path of our key according to the key origins in the PSBT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<DerivationPath>,
+
+    /// Sighash type requested for the signatures of the input, like `SIGHASH_ALL`
+    pub sighash: String,
+
+    /// Sequence number of the input
+    pub sequence: u32,
 
-    for psbt_file in psbts {
+    /// Relative timelock enabled by the sequence, like `10 blocks` or `5120 seconds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_timelock: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutputDetail {
+    /// Amount in satoshi
+    pub amount: u64,
+
+    /// Missing for scripts without address like OP_RETURN or bare scripts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+
+    /// Script pubkey in hex
+    pub script_pubkey: ScriptBuf,
+
+    /// Type of the script pubkey, like `p2wpkh`, `p2tr`, `p2a`, `op_return` or `nonstandard`
+    pub script_type: String,
+
+    /// Data pushed by an OP_RETURN output in hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub op_return: Option<String>,
+
+    pub ownership: Ownership,
+
+    /// Descriptor type, like `Wpkh` or `Tr`, when the output is ours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desc_type: Option<String>,
+
+    /// Derivation path of our key according to the key origins in the PSBT
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<DerivationPath>,
+}
+
+pub fn main(seed: &Seed, params: Params) -> Result<Vec<Output>, Error> {
+    let mut psbts = vec![];
+    let mut data = Vec::new();
+    for psbt_file in params.psbts.iter() {
         data.clear();
         std::fs::File::open(psbt_file)?
             .read_to_end(&mut data)
             .expect("Unable to read data");
-        let mut psbt: Psbt = match Psbt::deserialize(&data[..]) {
+        let psbt: Psbt = match Psbt::deserialize(&data[..]) {
             Ok(s) => s,
             Err(_) => {
                 let s = std::str::from_utf8(&data)?;
@@ -102,16 +264,70 @@ pub fn main(seed: &Seed, params: Params) -> Result<Vec<Output>, Error> {
                 s_no_control_char.parse()?
             }
         };
+        psbts.push((psbt_file.clone(), psbt));
+    }
+    sign_psbts(seed, params, psbts)
+}
+
+/// Check and sign the PSBTs with the options of `params`, the file of every PSBT is used only to
+/// name the binary copy
+pub(crate) fn sign_psbts(
+    seed: &Seed,
+    params: Params,
+    psbts: Vec<(PathBuf, Psbt)>,
+) -> Result<Vec<Output>, Error> {
+    let Params {
+        psbts: _,
+        require_prev_tx,
+        max_fee,
+        max_fee_rate,
+        max_fee_percent,
+        allow_any_sighash,
+        policy,
+        audit_log,
+        signed_outpoints,
+        allow_replace,
+        text: _,
+        sign_only,
+        binary_dir,
+        wallet:
+            WalletParams {
+                network,
+                max,
+                accounts,
+                descriptor,
+            },
+    } = params;
+    let require_prev_tx = require_prev_tx.unwrap_or(network == Network::Bitcoin);
+    let policy = match policy {
+        Some(path) => Some(Policy::load(&path)?.prepare(network, max)?),
+        None => None,
+    };
+    let mut record = match signed_outpoints.as_ref() {
+        Some(path) => Some(SignedOutpoints::load(path)?),
+        None => None,
+    };
 
-        // sign
-        let signatures = psbt.sign(&xpriv, &secp).map_err(debug_to_string)?;
+    let secp = Secp256k1::new();
 
-        let mut signatures_added = 0;
-        for inp in signatures.values() {
-            signatures_added += match inp {
-                SigningKeys::Ecdsa(a) => a.len(),
-                SigningKeys::Schnorr(a) => a.len(),
-            };
+    let descriptors = compute_finite_descriptors(seed, network, &secp, 0..accounts, &descriptor)?;
+    // addresses are generated only if some input lacks key origins
+    let mut scanner = Scanner {
+        script_pubkeys: None,
+        descriptors: &descriptors,
+        max,
+        network,
+    };
+
+    let xpriv = seed.xprv(network);
+    let fingerprint = xpriv.fingerprint(&secp);
+
+    let mut results = vec![];
+
+    for (psbt_file, mut psbt) in psbts {
+        check_prev_txs(&psbt, require_prev_tx)?;
+        if !allow_any_sighash {
+            check_sighash(&psbt, fingerprint)?;
         }
 
         let mut sum_input = 0;
@@ -122,133 +338,850 @@ pub fn main(seed: &Seed, params: Params) -> Result<Vec<Output>, Error> {
 
         let mut inputs = vec![];
         for (input_idx, input) in psbt.inputs.iter().enumerate() {
-            match input.witness_utxo.as_ref() {
-                Some(txout) => {
-                    let prev_address = Address::from_script(&txout.script_pubkey, network)?;
-                    let amount = txout.value.to_sat();
-                    let is_mine = script_pubkeys.contains_key(&txout.script_pubkey);
-                    sum_input += amount;
-                    if is_mine {
-                        sum_my_input += amount;
-                    }
-                    let is_mine = if is_mine { " mine" } else { "" };
-                    inputs.push(format!("{amount:>10}:{prev_address}{is_mine}"));
+            let txout = match (input.witness_utxo.as_ref(), input.non_witness_utxo.as_ref()) {
+                (Some(txout), _) => txout,
+                (None, Some(tx)) => {
+                    let prev_out_idx =
+                        psbt.unsigned_tx.input[input_idx].previous_output.vout as usize;
+                    &tx.output[prev_out_idx]
                 }
-                None => match input.non_witness_utxo.as_ref() {
-                    Some(tx) => {
-                        let prev_out_idx =
-                            psbt.unsigned_tx.input[input_idx].previous_output.vout as usize;
-                        let tx_out = &tx.output[prev_out_idx];
-                        let amount = tx_out.value.to_sat();
-                        let prev_address = Address::from_script(&tx_out.script_pubkey, network)?;
-
-                        sum_input += amount;
-                        let is_mine = script_pubkeys.contains_key(&tx_out.script_pubkey);
-                        if is_mine {
-                            sum_my_input += amount;
-                        }
-                        let is_mine = if is_mine { " mine" } else { "" };
-                        inputs.push(format!("{amount:>10}:{prev_address}{is_mine}"));
-                    }
-                    None => {
-                        return Err(Error::Other(
-                            "neither witness_utxo nor non_witness_utxo are set",
-                        ))
-                    }
-                },
+                (None, None) => {
+                    return Err(Error::Other(
+                        "neither witness_utxo nor non_witness_utxo are set",
+                    ))
+                }
+            };
+            let amount = txout.value.to_sat();
+            let key_origins = KeyOrigins::from(input);
+            let owner = key_origins.owner(&txout.script_pubkey, &xpriv, &secp, &descriptors);
+            let owner = scanner.owner(owner, &key_origins, &txout.script_pubkey)?;
+            sum_input += amount;
+            if owner.ownership == Ownership::Ours {
+                sum_my_input += amount;
             }
+            let txin = &psbt.unsigned_tx.input[input_idx];
+            let relative_timelock = txin.sequence.to_relative_lock_time().map(|l| match l {
+                relative::LockTime::Blocks(h) => format!("{} blocks", h.value()),
+                relative::LockTime::Time(t) => format!("{} seconds", t.value() as u32 * 512),
+            });
+            inputs.push(InputDetail {
+                outpoint: txin.previous_output,
+                amount,
+                address: address(&txout.script_pubkey, network),
+                script_pubkey: txout.script_pubkey.clone(),
+                script_type: script_type(&txout.script_pubkey).to_string(),
+                ownership: owner.ownership,
+                desc_type: owner.desc_type.map(|t| format!("{t:?}")),
+                path: owner.path,
+                sighash: sighash(input, &txout.script_pubkey),
+                sequence: txin.sequence.to_consensus_u32(),
+                relative_timelock,
+            });
         }
         let mut outputs = vec![];
-        for (_psbt_output, txout) in psbt.outputs.iter().zip(psbt.unsigned_tx.output.iter()) {
-            let address = Address::from_script(&txout.script_pubkey, network)?;
+        let mut fake_outputs = vec![];
+        for (output_idx, (psbt_output, txout)) in psbt
+            .outputs
+            .iter()
+            .zip(psbt.unsigned_tx.output.iter())
+            .enumerate()
+        {
             let amount = txout.value.to_sat();
-            let is_mine = script_pubkeys.contains_key(&txout.script_pubkey);
+            let key_origins = KeyOrigins::from(psbt_output);
+            // outputs without key origins, like every recipient, are foreign without scanning the
+            // addresses: a change without key origins only lowers `bal`
+            let owner = key_origins.owner(&txout.script_pubkey, &xpriv, &secp, &descriptors);
 
             sum_output += amount;
-            if is_mine {
-                sum_my_output += amount;
-            }
-            let is_mine = if is_mine { " mine" } else { "" };
-            outputs.push(format!("{amount:>10}:{address}{is_mine}"));
-        }
-
-        for (input, sign_keys) in psbt.inputs.iter_mut().zip(signatures.values()) {
-            if input.witness_utxo.is_some() {
-                let script_witness = match sign_keys {
-                    SigningKeys::Schnorr(_) => {
-                        let tap_key_sig = input
-                            .tap_key_sig
-                            .as_ref()
-                            .expect("schnorr sig without tap_key_sig");
-                        Witness::p2tr_key_spend(tap_key_sig)
-                    }
-                    SigningKeys::Ecdsa(sign_keys) => {
-                        let sign_key = sign_keys.iter().next().expect("we have one sig");
-                        let (_, sig) = input.partial_sigs.iter().next().expect("we have one sig");
-                        Witness::p2wpkh(sig, &sign_key.inner)
+            match owner.ownership {
+                Ownership::Ours => sum_my_output += amount,
+                Ownership::Fake => fake_outputs.push(output_idx),
+                Ownership::Foreign | Ownership::Script => (),
+            }
+            outputs.push(OutputDetail {
+                amount,
+                address: address(&txout.script_pubkey, network),
+                script_pubkey: txout.script_pubkey.clone(),
+                script_type: script_type(&txout.script_pubkey).to_string(),
+                op_return: op_return_data(&txout.script_pubkey),
+                ownership: owner.ownership,
+                desc_type: owner.desc_type.map(|t| format!("{t:?}")),
+                path: owner.path,
+            });
+        }
+
+        let fee = sum_input
+            .checked_sub(sum_output)
+            .ok_or(Error::Other("outputs exceed inputs"))?;
+        check_fee(fee, sum_output - sum_my_output, max_fee, max_fee_percent)?;
+        if let Some(policy) = policy.as_ref() {
+            let lock_time = psbt.unsigned_tx.lock_time.to_consensus_u32();
+            policy.check(&outputs, fee, lock_time)?;
+        }
+        let replaced_txids = match record.as_ref() {
+            Some(record) => record.conflicts(&psbt.unsigned_tx),
+            None => vec![],
+        };
+        if !replaced_txids.is_empty() && !allow_replace {
+            let txids: Vec<_> = replaced_txids.iter().map(Txid::to_string).collect();
+            return Err(format!(
+                "inputs already signed in the transactions with unsigned txid {}, use --allow-replace to sign a replacement",
+                txids.join(", ")
+            )
+            .into());
+        }
+
+        let mut estimated_fee_rate = None;
+        let mut unestimated_inputs = vec![];
+        if let Some(max_fee_rate) = max_fee_rate {
+            let (weight, unestimated) = estimate_weight(&psbt, &descriptors)?;
+            let fee_rate = fee as f64 / weight.to_vbytes_ceil() as f64;
+            if fee_rate > max_fee_rate {
+                let unestimated = match unestimated.as_slice() {
+                    [] => String::new(),
+                    idxs => {
+                        format!(", inputs {idxs:?} are counted without their unknown satisfaction")
                     }
                 };
-                input.final_script_witness = Some(script_witness); // for tr, segwit and nested segwit
-
-                if let Some(redeem_script) = input.redeem_script.as_ref() {
-                    // for nested segwit
-                    let script_sig = script::Builder::new()
-                        .push_slice(<&PushBytes>::try_from(redeem_script.as_bytes()).unwrap())
-                        .into_script();
-                    input.final_script_sig = Some(script_sig);
+                return Err(format!(
+                    "estimated fee rate {fee_rate:.2} sat/vB is higher than the maximum {max_fee_rate}{unestimated}"
+                )
+                .into());
+            }
+            estimated_fee_rate = Some(fee_rate);
+            unestimated_inputs = unestimated;
+        }
+
+        for input in psbt.inputs.iter_mut() {
+            add_missing_leaf_hashes(input, fingerprint);
+        }
+
+        // sign, errors are tolerated only on inputs of other participants
+        let signatures = match psbt.sign(&xpriv, &secp) {
+            Ok(signatures) => signatures,
+            Err((signatures, errors)) => {
+                for (input_idx, e) in errors {
+                    if has_our_keys(&psbt.inputs[input_idx], fingerprint) {
+                        return Err(format!("input #{input_idx}: {e:?}").into());
+                    }
                 }
+                signatures
+            }
+        };
+
+        let mut signatures_added = 0;
+        let mut signed_inputs = vec![];
+        let mut unsigned_inputs = vec![];
+        for input_idx in 0..psbt.inputs.len() {
+            let added = match signatures.get(&input_idx) {
+                Some(SigningKeys::Ecdsa(a)) => a.len(),
+                Some(SigningKeys::Schnorr(a)) => a.len(),
+                None => 0,
+            };
+            signatures_added += added;
+            if added > 0 {
+                signed_inputs.push(input_idx);
             } else {
-                // for legacy
-                let (pubkey, sig) = input.partial_sigs.iter().next().expect("we have one sig");
+                unsigned_inputs.push(input_idx);
+            }
+        }
 
-                let script_sig = script::Builder::new()
-                    .push_slice(&sig.serialize())
-                    .push_slice(&pubkey.inner.serialize())
-                    .into_script();
-                input.final_script_sig = Some(script_sig);
+        for input_idx in 0..psbt.inputs.len() {
+            if sign_only {
+                break;
+            }
+            let input = &psbt.inputs[input_idx];
+            if !has_our_keys(input, fingerprint)
+                || input.final_script_witness.is_some()
+                || input.final_script_sig.is_some()
+            {
+                // inputs of other participants are left untouched
+                continue;
+            }
+            // the finalizer checks the resulting witness and script sig with the miniscript
+            // interpreter against the spent script
+            match psbt.finalize_inp_mut(&secp, input_idx) {
+                Ok(()) => (),
+                Err(psbt::Error::InputError(
+                    InputError::CouldNotSatisfyTr
+                    | InputError::MiniscriptError(miniscript::Error::CouldNotSatisfy),
+                    _,
+                )) => {
+                    // not yet satisfiable, like a multisig missing signatures of other
+                    // cosigners, our partial signatures are left in the PSBT
+                }
+                Err(e) => return Err(e.into()),
             }
+        }
 
-            // Clear all the data fields as per the spec.
-            input.partial_sigs = BTreeMap::new();
-            input.sighash_type = None;
-            input.redeem_script = None;
-            input.witness_script = None;
-            input.bip32_derivation = BTreeMap::new();
+        let finalized = psbt
+            .inputs
+            .iter()
+            .all(|i| i.final_script_sig.is_some() || i.final_script_witness.is_some());
+        let tx = if finalized {
+            Some(psbt.clone().extract_tx()?)
+        } else {
+            None
+        };
+        let weight = tx.as_ref().map(|tx| tx.weight().to_wu());
+        let vsize = tx.as_ref().map(|tx| tx.vsize() as u64);
+        let fee_rate = vsize.map(|vsize| fee as f64 / vsize as f64);
+        if let (Some(fee_rate), Some(max_fee_rate)) = (fee_rate, max_fee_rate) {
+            if fee_rate > max_fee_rate {
+                return Err(format!(
+                    "fee rate {fee_rate:.2} sat/vB is higher than the maximum {max_fee_rate}"
+                )
+                .into());
+            }
         }
 
         let psbt_base64 = psbt.to_string();
-        let tx = psbt.extract_tx()?;
-        let txid = tx.compute_txid();
-        let tx_hex = serialize_hex(&tx);
-        println!("sum_my_output:{sum_my_output} sum_my_input:{sum_my_input}");
+        if let Some(binary_dir) = binary_dir.as_ref() {
+            let stem = psbt_file
+                .file_stem()
+                .ok_or(Error::Other("psbt file without name"))?
+                .to_string_lossy();
+            std::fs::write(
+                binary_dir.join(format!("{stem}.signed.psbt")),
+                psbt.serialize(),
+            )?;
+        }
         let bal = sum_my_output as i64 - sum_my_input as i64;
 
-        results.push(Output {
-            tx: tx_hex,
+        let output = Output {
+            tx: tx.as_ref().map(serialize_hex),
             psbt: psbt_base64,
-            txid,
+            txid: tx.as_ref().map(Transaction::compute_txid),
+            weight,
+            vsize,
+            fee_rate,
             inputs,
             outputs,
-            fee: format!("{:>10}", sum_input - sum_output),
-            bal: format!("{:>10}", bal),
+            lock_time: psbt.unsigned_tx.lock_time.to_consensus_u32(),
+            fee,
+            bal,
             signatures_added,
-        });
+            signed_inputs,
+            unsigned_inputs,
+            fake_outputs,
+            estimated_fee_rate,
+            unestimated_inputs,
+            replaced_txids,
+        };
+        if let (Some(record), Some(path)) = (record.as_mut(), signed_outpoints.as_ref()) {
+            if !output.signed_inputs.is_empty() {
+                record.insert(&psbt.unsigned_tx, &output.signed_inputs);
+                record.save(path)?;
+            }
+        }
+        if let Some(audit_log) = audit_log.as_ref() {
+            verify_log::append(audit_log, &output)?;
+        }
+        results.push(output);
     }
     Ok(results)
 }
 
+/// Address of the script pubkey, if it has one
+fn address(script_pubkey: &Script, network: Network) -> Option<String> {
+    Address::from_script(script_pubkey, network)
+        .ok()
+        .map(|a| a.to_string())
+}
+
+/// Pay to anchor script pubkey `OP_1 <0x4e73>`
+const P2A: [u8; 4] = [0x51, 0x02, 0x4e, 0x73];
+
+fn script_type(script_pubkey: &Script) -> &'static str {
+    if script_pubkey.is_p2pkh() {
+        "p2pkh"
+    } else if script_pubkey.is_p2sh() {
+        "p2sh"
+    } else if script_pubkey.is_p2wpkh() {
+        "p2wpkh"
+    } else if script_pubkey.is_p2wsh() {
+        "p2wsh"
+    } else if script_pubkey.is_p2tr() {
+        "p2tr"
+    } else if script_pubkey.as_bytes() == P2A {
+        "p2a"
+    } else if script_pubkey.is_op_return() {
+        "op_return"
+    } else if script_pubkey.is_p2pk() {
+        "p2pk"
+    } else if script_pubkey.is_multisig() {
+        "multisig"
+    } else if script_pubkey.is_witness_program() {
+        "witness_unknown"
+    } else {
+        "nonstandard"
+    }
+}
+
+/// Data pushed after the OP_RETURN, concatenated and hex encoded
+fn op_return_data(script_pubkey: &Script) -> Option<String> {
+    if !script_pubkey.is_op_return() {
+        return None;
+    }
+    let data: Vec<u8> = script_pubkey
+        .instructions()
+        .skip(1)
+        .filter_map(|i| match i {
+            Ok(Instruction::PushBytes(p)) => Some(p.as_bytes().to_vec()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    Some(data.to_lower_hex_string())
+}
+
+/// Sighash type of the input, when missing the default is ALL for ecdsa and DEFAULT for taproot
+fn sighash(input: &bitcoin::psbt::Input, script_pubkey: &Script) -> String {
+    let sighash = if script_pubkey.is_p2tr() {
+        input.taproot_hash_ty().map(|t| t.to_string()).ok()
+    } else {
+        input.ecdsa_hash_ty().map(|t| t.to_string()).ok()
+    };
+    sighash.unwrap_or_else(|| format!("{:#x}", input.sighash_type.map_or(0, |t| t.to_u32())))
+}
+
+/// Our inputs can be signed only with sighash ALL or DEFAULT, other types let a malicious PSBT
+/// creator reuse our signatures in different transactions, for example with SINGLE|ANYONECANPAY
+/// an input could be moved in a transaction sending our funds elsewhere
+fn check_sighash(psbt: &Psbt, fingerprint: Fingerprint) -> Result<(), Error> {
+    for (input_idx, input) in psbt.inputs.iter().enumerate() {
+        if !has_our_keys(input, fingerprint) {
+            continue;
+        }
+        if let Some(sighash_type) = input.sighash_type {
+            let all = EcdsaSighashType::All as u32;
+            let default = TapSighashType::Default as u32;
+            if sighash_type.to_u32() != all && sighash_type.to_u32() != default {
+                return Err(format!(
+                    "input #{input_idx}: sighash {sighash_type} not allowed without --allow-any-sighash"
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fee guard rails against fat-fingered PSBTs, `sent` is the amount sent to others
+fn check_fee(
+    fee: u64,
+    sent: u64,
+    max_fee: Option<u64>,
+    max_fee_percent: Option<f64>,
+) -> Result<(), Error> {
+    if let Some(max_fee) = max_fee {
+        if fee > max_fee {
+            return Err(format!("fee {fee} is higher than the maximum {max_fee}").into());
+        }
+    }
+    if let Some(max_fee_percent) = max_fee_percent {
+        // consolidations and self-transfers don't send anything
+        let percent = fee as f64 * 100.0 / sent as f64;
+        if sent > 0 && percent > max_fee_percent {
+            return Err(format!(
+                "fee {fee} is {percent:.2}% of the amount sent {sent}, higher than the maximum {max_fee_percent}%"
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Upper bound of the weight of the transaction once all the inputs are satisfied. Finalized
+/// inputs count with their final script sig and witness, the others with the maximum
+/// satisfaction weight of the descriptor they spend, found at the index of their key origins,
+/// or with [`foreign_max_weight`]. Inputs whose satisfaction is unknown are counted without it
+/// and their indexes returned, in that case the weight may be lower than the final one.
+fn estimate_weight(
+    psbt: &Psbt,
+    descriptors: &[Descriptor<DescriptorPublicKey>],
+) -> Result<(Weight, Vec<usize>), Error> {
+    // segwit marker and flag
+    let mut weight = psbt.unsigned_tx.weight() + Weight::from_wu(2);
+    let mut unestimated = vec![];
+    for (input_idx, (input, txin)) in psbt.inputs.iter().zip(&psbt.unsigned_tx.input).enumerate() {
+        let satisfaction = if input.final_script_sig.is_some()
+            || input.final_script_witness.is_some()
+        {
+            let mut satisfied = txin.clone();
+            satisfied.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            satisfied.witness = input.final_script_witness.clone().unwrap_or_default();
+            satisfied.segwit_weight() - txin.segwit_weight()
+        } else {
+            let script_pubkey = match (input.witness_utxo.as_ref(), input.non_witness_utxo.as_ref())
+            {
+                (Some(txout), _) => &txout.script_pubkey,
+                (None, Some(tx)) => &tx.output[txin.previous_output.vout as usize].script_pubkey,
+                (None, None) => unreachable!("checked before"),
+            };
+            let paths = input
+                .bip32_derivation
+                .values()
+                .chain(input.tap_key_origins.values().map(|(_, s)| s));
+            let definite = paths
+                .filter_map(|(_, path)| last_index(path))
+                .flat_map(|index| descriptors.iter().map(move |d| (d, index)))
+                .filter_map(|(d, index)| d.at_derivation_index(index).ok())
+                .find(|d| d.script_pubkey() == *script_pubkey);
+            match definite {
+                Some(definite) => definite.max_weight_to_satisfy()?,
+                None => foreign_max_weight(input, script_pubkey).unwrap_or_else(|| {
+                    unestimated.push(input_idx);
+                    Weight::ZERO
+                }),
+            }
+        };
+        // the witness count of every input
+        weight += Weight::from_wu(1) + satisfaction;
+    }
+    Ok((weight, unestimated))
+}
+
+/// Maximum satisfaction weight of an input not spending our descriptors. Scripts in the PSBT
+/// (`witness_script`, `redeem_script`, `tap_scripts`) are parsed as miniscript and must reproduce
+/// the script pubkey, single key script pubkeys count with their worst case, like an
+/// uncompressed key for p2pkh. `None` if the satisfaction is unknown, like a p2wsh without the
+/// witness script or a taproot output whose scripts are not in the PSBT.
+fn foreign_max_weight(input: &bitcoin::psbt::Input, script_pubkey: &Script) -> Option<Weight> {
+    // signatures of 73 bytes and keys with their length prefix, like miniscript
+    const P2WPKH: u64 = 73 + 34;
+    const P2PKH: u64 = (73 + 66) * 4;
+    const P2SH_P2WPKH: u64 = 23 * 4 + P2WPKH;
+    const P2TR_KEY_SPEND: u64 = 1 + 65;
+
+    let secp = Secp256k1::verification_only();
+    let matching = |desc: Descriptor<bitcoin::PublicKey>| {
+        (desc.script_pubkey() == *script_pubkey)
+            .then(|| desc.max_weight_to_satisfy().ok())
+            .flatten()
+    };
+    let witness_script = || {
+        input
+            .witness_script
+            .as_ref()
+            .and_then(|ws| Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_insane(ws).ok())
+    };
+
+    if script_pubkey.is_p2wpkh() {
+        Some(Weight::from_wu(P2WPKH))
+    } else if script_pubkey.is_p2pkh() {
+        Some(Weight::from_wu(P2PKH))
+    } else if script_pubkey.is_p2wsh() {
+        matching(Descriptor::new_wsh(witness_script()?).ok()?)
+    } else if script_pubkey.is_p2sh() {
+        let rs = input.redeem_script.as_ref()?;
+        if ScriptBuf::new_p2sh(&rs.script_hash()) != *script_pubkey {
+            None
+        } else if rs.is_p2wpkh() {
+            Some(Weight::from_wu(P2SH_P2WPKH))
+        } else if rs.is_p2wsh() {
+            matching(Descriptor::new_sh_wsh(witness_script()?).ok()?)
+        } else {
+            let ms = Miniscript::<bitcoin::PublicKey, Legacy>::parse_insane(rs).ok()?;
+            matching(Descriptor::new_sh(ms).ok()?)
+        }
+    } else if script_pubkey.is_p2tr() {
+        // with scripts the merkle root must be given, otherwise the key spend is the only one
+        let internal = input.tap_internal_key?;
+        if ScriptBuf::new_p2tr(&secp, internal, input.tap_merkle_root) != *script_pubkey {
+            return None;
+        }
+        if input.tap_merkle_root.is_some() && input.tap_scripts.is_empty() {
+            return None;
+        }
+        let mut max = P2TR_KEY_SPEND;
+        for (control_block, (script, _)) in input.tap_scripts.iter() {
+            let ms = Miniscript::<XOnlyPublicKey, Tap>::parse_insane(script).ok()?;
+            let control_block = control_block.size();
+            let leaf = ms.max_satisfaction_size().ok()?
+                + VarInt::from(script.len()).size()
+                + script.len()
+                + VarInt::from(control_block).size()
+                + control_block;
+            max = max.max(leaf as u64);
+        }
+        Some(Weight::from_wu(max))
+    } else {
+        None
+    }
+}
+
+/// Previous transactions must match the outpoints spent and the `witness_utxo` if present. If
+/// `require` is true, segwit v0 and legacy inputs must have the previous transaction, because
+/// their signatures don't commit to the amounts of the other inputs and a fee could be faked.
+fn check_prev_txs(psbt: &Psbt, require: bool) -> Result<(), Error> {
+    for (input_idx, (input, txin)) in psbt.inputs.iter().zip(&psbt.unsigned_tx.input).enumerate() {
+        let outpoint = txin.previous_output;
+        match input.non_witness_utxo.as_ref() {
+            Some(prev_tx) => {
+                if prev_tx.compute_txid() != outpoint.txid {
+                    return Err(format!(
+                        "input #{input_idx}: previous transaction doesn't match the txid {}",
+                        outpoint.txid
+                    )
+                    .into());
+                }
+                let prev_out = prev_tx.output.get(outpoint.vout as usize).ok_or_else(|| {
+                    format!("input #{input_idx}: previous transaction without output {outpoint}")
+                })?;
+                if input.witness_utxo.as_ref().is_some_and(|w| w != prev_out) {
+                    return Err(format!(
+                        "input #{input_idx}: witness_utxo doesn't match the previous transaction"
+                    )
+                    .into());
+                }
+            }
+            None => {
+                let taproot = input
+                    .witness_utxo
+                    .as_ref()
+                    .is_some_and(|w| w.script_pubkey.is_p2tr());
+                if require && !taproot {
+                    return Err(format!(
+                        "input #{input_idx}: missing the previous transaction (non_witness_utxo)"
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Some PSBT creators don't set the leaf hashes in the key origins of the keys used in taproot
+/// script paths, without them the signer doesn't provide script path signatures. We add the hashes
+/// of the leaves containing our keys, excluding the internal key to keep the key path spend.
+fn add_missing_leaf_hashes(input: &mut bitcoin::psbt::Input, fingerprint: Fingerprint) {
+    let leaves: Vec<_> = input
+        .tap_scripts
+        .values()
+        .map(|(script, ver)| (script, TapLeafHash::from_script(script, *ver)))
+        .collect();
+    for (xonly, (leaf_hashes, (f, _))) in input.tap_key_origins.iter_mut() {
+        if *f != fingerprint || input.tap_internal_key == Some(*xonly) {
+            continue;
+        }
+        let key = xonly.serialize();
+        for (script, leaf_hash) in leaves.iter() {
+            if contains_key(script, &key) && !leaf_hashes.contains(leaf_hash) {
+                leaf_hashes.push(*leaf_hash);
+            }
+        }
+    }
+}
+
+/// Returns true if the script pushes the given key
+fn contains_key(script: &Script, key: &[u8]) -> bool {
+    script
+        .instructions()
+        .any(|i| matches!(i, Ok(Instruction::PushBytes(p)) if p.as_bytes() == key))
+}
+
+/// Ownership of an input or an output according to the key origins in the PSBT
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Ownership {
+    /// No key origins of our seed
+    Foreign,
+
+    /// Keys of our seed, re-derived from their origins, reproduce the script pubkey with one of our
+    /// descriptors
+    Ours,
+
+    /// The script pubkey is a script containing a key of our seed together with other conditions
+    /// not matching our descriptors
+    Script,
+
+    /// Key origins of our seed don't derive the claimed key or don't reproduce the script pubkey
+    /// with our descriptors
+    Fake,
+}
+
+/// Ownership of a script pubkey with the descriptor type and the derivation path of our key
+struct Owner {
+    ownership: Ownership,
+    desc_type: Option<DescriptorType>,
+    path: Option<DerivationPath>,
+}
+
+/// The fields of PSBT inputs and outputs used to check the ownership of their script pubkey
+struct KeyOrigins<'a> {
+    bip32_derivation: &'a BTreeMap<bitcoin::secp256k1::PublicKey, KeySource>,
+    tap_key_origins: &'a TapKeyOrigin,
+    witness_script: Option<&'a ScriptBuf>,
+    redeem_script: Option<&'a ScriptBuf>,
+    tap_internal_key: Option<XOnlyPublicKey>,
+    tap_merkle_root: Option<TapNodeHash>,
+    tap_leaves: Vec<ScriptBuf>,
+}
+
+impl KeyOrigins<'_> {
+    /// Returns true if there are key origins of any participant
+    fn is_some(&self) -> bool {
+        !self.bip32_derivation.is_empty() || !self.tap_key_origins.is_empty()
+    }
+}
+
+impl<'a> From<&'a bitcoin::psbt::Input> for KeyOrigins<'a> {
+    fn from(input: &'a bitcoin::psbt::Input) -> Self {
+        KeyOrigins {
+            bip32_derivation: &input.bip32_derivation,
+            tap_key_origins: &input.tap_key_origins,
+            witness_script: input.witness_script.as_ref(),
+            redeem_script: input.redeem_script.as_ref(),
+            tap_internal_key: input.tap_internal_key,
+            tap_merkle_root: input.tap_merkle_root,
+            tap_leaves: input.tap_scripts.values().map(|(s, _)| s.clone()).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a bitcoin::psbt::Output> for KeyOrigins<'a> {
+    fn from(output: &'a bitcoin::psbt::Output) -> Self {
+        KeyOrigins {
+            bip32_derivation: &output.bip32_derivation,
+            tap_key_origins: &output.tap_key_origins,
+            witness_script: output.witness_script.as_ref(),
+            redeem_script: output.redeem_script.as_ref(),
+            tap_internal_key: output.tap_internal_key,
+            tap_merkle_root: output.tap_tree.as_ref().map(|t| t.root_hash()),
+            tap_leaves: output
+                .tap_tree
+                .iter()
+                .flat_map(|t| t.script_leaves().map(|l| l.script().to_owned()))
+                .collect(),
+        }
+    }
+}
+
+impl KeyOrigins<'_> {
+    /// Re-derive the keys having our fingerprint in the key origins and check they reproduce the
+    /// script pubkey with our descriptors at the derivation index of the origin, or as part of the
+    /// witness, redeem or taproot scripts. A key of our seed at a path outside our descriptors,
+    /// for example a p2pkh of a bip84 key or an account not scanned, is not ours because it's not
+    /// monitored by the watch-only wallet.
+    fn owner(
+        &self,
+        script_pubkey: &Script,
+        xpriv: &Xpriv,
+        secp: &Secp256k1<All>,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+    ) -> Owner {
+        let fingerprint = xpriv.fingerprint(secp);
+        let derive = |path: &DerivationPath| {
+            let derived = xpriv.derive_priv(secp, path).expect(
+                "statistically impossible to hit, Result will be removed in next rust bitcoin version",
+            );
+            Xpub::from_priv(secp, &derived).public_key
+        };
+        let owner = |ownership, desc_type, path: Option<&DerivationPath>| Owner {
+            ownership,
+            desc_type,
+            path: path.cloned(),
+        };
+
+        let mut claimed = None;
+        let mut indexes = vec![];
+        let mut in_script = false;
+        for (pk, (f, path)) in self.bip32_derivation.iter() {
+            if *f != fingerprint {
+                continue;
+            }
+            claimed = Some(path);
+            if derive(path) != *pk {
+                return owner(Ownership::Fake, None, Some(path));
+            }
+            indexes.extend(last_index(path).map(|i| (i, path)));
+
+            let key = CompressedPublicKey(*pk).to_bytes();
+            if let Some(ws) = self.witness_script {
+                let wsh = ScriptBuf::new_p2wsh(&ws.wscript_hash());
+                in_script |= contains_key(ws, &key)
+                    && (wsh == *script_pubkey
+                        || ScriptBuf::new_p2sh(&wsh.script_hash()) == *script_pubkey);
+            }
+            if let Some(rs) = self.redeem_script {
+                in_script |= contains_key(rs, &key)
+                    && ScriptBuf::new_p2sh(&rs.script_hash()) == *script_pubkey;
+            }
+        }
+        for (xonly, (_, (f, path))) in self.tap_key_origins.iter() {
+            if *f != fingerprint {
+                continue;
+            }
+            claimed = Some(path);
+            if derive(path).x_only_public_key().0 != *xonly {
+                return owner(Ownership::Fake, None, Some(path));
+            }
+            indexes.extend(last_index(path).map(|i| (i, path)));
+
+            if let Some(internal) = self.tap_internal_key {
+                let key = xonly.serialize();
+                in_script |= (internal == *xonly
+                    || self.tap_leaves.iter().any(|l| contains_key(l, &key)))
+                    && ScriptBuf::new_p2tr(secp, internal, self.tap_merkle_root) == *script_pubkey;
+            }
+        }
+
+        let Some(claimed) = claimed else {
+            return owner(Ownership::Foreign, None, None);
+        };
+        for (index, path) in indexes {
+            for d in descriptors {
+                if let Ok(definite) = d.at_derivation_index(index) {
+                    if definite.script_pubkey() == *script_pubkey {
+                        return owner(Ownership::Ours, Some(definite.desc_type()), Some(path));
+                    }
+                }
+            }
+        }
+        if in_script {
+            owner(Ownership::Script, None, Some(claimed))
+        } else {
+            owner(Ownership::Fake, None, Some(claimed))
+        }
+    }
+}
+
+/// The last step of a derivation path, if not hardened, is the index of the descriptor
+fn last_index(path: &DerivationPath) -> Option<u32> {
+    match path.into_iter().last() {
+        Some(ChildNumber::Normal { index }) => Some(*index),
+        _ => None,
+    }
+}
+
+/// Checks ownership of inputs without key origins against the first `max` addresses of the
+/// descriptors, generated once at the first need
+struct Scanner<'a> {
+    script_pubkeys: Option<HashMap<ScriptBuf, DescriptorType>>,
+    descriptors: &'a [Descriptor<DescriptorPublicKey>],
+    max: u32,
+    network: Network,
+}
+
+impl Scanner<'_> {
+    /// An input without key origins, like one whose origins were not provided by the PSBT
+    /// creator, is ours if found in the scanned addresses
+    fn owner(
+        &mut self,
+        owner: Owner,
+        key_origins: &KeyOrigins,
+        script_pubkey: &Script,
+    ) -> Result<Owner, Error> {
+        if owner.ownership != Ownership::Foreign || key_origins.is_some() {
+            return Ok(owner);
+        }
+        let script_pubkeys = match self.script_pubkeys.as_ref() {
+            Some(s) => s,
+            None => {
+                let addresses = precompute_addresses(self.descriptors, self.max, self.network)?;
+                self.script_pubkeys.insert(
+                    addresses
+                        .into_iter()
+                        .map(|(addr, t)| (addr.script_pubkey(), t))
+                        .collect(),
+                )
+            }
+        };
+        Ok(match script_pubkeys.get(script_pubkey) {
+            Some(desc_type) => Owner {
+                ownership: Ownership::Ours,
+                desc_type: Some(*desc_type),
+                path: None,
+            },
+            None => owner,
+        })
+    }
+}
+
+/// Returns true if the input has key origins with our fingerprint
+fn has_our_keys(input: &bitcoin::psbt::Input, fingerprint: Fingerprint) -> bool {
+    input
+        .bip32_derivation
+        .values()
+        .any(|(f, _)| *f == fingerprint)
+        || input
+            .tap_key_origins
+            .values()
+            .any(|(_, (f, _))| *f == fingerprint)
+}
+
 impl Output {
-    pub fn tx(&self) -> Transaction {
-        let bytes = Vec::<u8>::from_hex(&self.tx).expect("guaranteed by invariant");
-        Transaction::consensus_decode(&mut &bytes[..]).expect("guaranteed by invariant")
+    pub fn tx(&self) -> Option<Transaction> {
+        let bytes = Vec::<u8>::from_hex(self.tx.as_ref()?).expect("guaranteed by invariant");
+        Some(Transaction::consensus_decode(&mut &bytes[..]).expect("guaranteed by invariant"))
     }
     pub fn psbt(&self) -> Psbt {
         Psbt::from_str(&self.psbt).expect("guaranteed by invariant")
     }
 }
 
+/// Human readable rendering of the inputs, outputs, fee and balance
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(txid) = self.txid.as_ref() {
+            writeln!(f, "txid: {txid}")?;
+        }
+        writeln!(f, "inputs:")?;
+        for input in self.inputs.iter() {
+            let ownership = input.ownership.suffix();
+            let destination = match input.address.as_ref() {
+                Some(address) => address.clone(),
+                None => format!("{}:{:x}", input.script_type, input.script_pubkey),
+            };
+            writeln!(f, "{:>10}:{destination}{ownership}", input.amount)?;
+        }
+        writeln!(f, "outputs:")?;
+        for output in self.outputs.iter() {
+            let ownership = output.ownership.suffix();
+            let destination = output.destination();
+            writeln!(f, "{:>10}:{destination}{ownership}", output.amount)?;
+        }
+        match (self.fee_rate, self.estimated_fee_rate) {
+            (Some(fee_rate), _) => writeln!(f, "fee: {} sat ({fee_rate:.2} sat/vB)", self.fee)?,
+            (None, Some(fee_rate)) if self.unestimated_inputs.is_empty() => {
+                writeln!(f, "fee: {} sat (estimated {fee_rate:.2} sat/vB)", self.fee)?
+            }
+            (None, Some(fee_rate)) => writeln!(
+                f,
+                "fee: {} sat (estimated {fee_rate:.2} sat/vB without the satisfaction of inputs {:?})",
+                self.fee, self.unestimated_inputs
+            )?,
+            (None, None) => writeln!(f, "fee: {} sat", self.fee)?,
+        }
+        write!(f, "bal: {} sat", self.bal)
+    }
+}
+
+impl OutputDetail {
+    /// The address, or the OP_RETURN data, or the script type and the script pubkey in hex
+    pub(crate) fn destination(&self) -> String {
+        match (self.address.as_ref(), self.op_return.as_ref()) {
+            (Some(address), _) => address.clone(),
+            (None, Some(data)) => format!("op_return:{data}"),
+            (None, None) => format!("{}:{:x}", self.script_type, self.script_pubkey),
+        }
+    }
+}
+
+impl Ownership {
+    pub(crate) fn suffix(&self) -> &'static str {
+        match self {
+            Ownership::Ours => " mine",
+            Ownership::Fake => " possibly fake change",
+            Ownership::Foreign | Ownership::Script => "",
+        }
+    }
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
 
     const BIP86_DERIVATION_PATH: &str = include_str!("../../wallet/bip86_derivation_path");
     // const BIP86_DERIVATION_PATH_TESTNET: &str =
@@ -272,18 +1205,20 @@ mod test {
         bip32::{DerivationPath, Fingerprint, Xpub},
         hashes::Hash,
         key::Secp256k1,
-        transaction, Amount, Network, OutPoint, Psbt, ScriptBuf, Sequence, TapSighashType,
-        Transaction, TxIn, TxOut, Txid, Witness,
+        transaction, Amount, EcdsaSighashType, Network, OutPoint, Psbt, ScriptBuf, Sequence,
+        TapSighashType, Transaction, TxIn, TxOut, Txid, Witness,
     };
     use bitcoin::{consensus, Address, TapLeafHash, XOnlyPublicKey};
     use std::collections::BTreeMap;
     use std::io::Write;
+    use std::path::Path;
     use tempfile::NamedTempFile;
 
+    use miniscript::psbt::PsbtExt;
     use miniscript::{Descriptor, DescriptorPublicKey};
 
     use crate::seed::Seed;
-    use crate::sign::{self, Params};
+    use crate::sign::{self, Ownership, Params};
 
     // The dummy UTXO amounts we are spending.
     const DUMMY_UTXO_AMOUNT_INPUT_1: Amount = Amount::from_sat(20_000_000);
@@ -403,22 +1338,16 @@ mod test {
         let serialized_unsigned_tx = consensus::encode::serialize_hex(&unsigned_tx);
         assert_eq!(178, serialized_unsigned_tx.len() / 2);
 
-        let mut f = NamedTempFile::new().expect("test");
-        f.as_file_mut()
-            .write_all(psbt.to_string().as_bytes())
-            .expect("Unable to write data");
+        let f = psbt_file(&psbt);
 
         // Step 3: Signer role; that signs the PSBT.
         // Step 4: Finalizer role; that finalizes the PSBT.
         // This steps changed in comparison of the original test and unified in the firma::main call
-        let params = Params {
-            psbts: vec![f.path().to_path_buf()],
-            network: Network::Bitcoin,
-            max: 20,
-        };
+        let params = sign_params(f.path(), Network::Bitcoin);
         let sign::Output { tx, psbt: _, .. } = sign::main(&seed, params).expect("test").remove(0);
 
         // BOOM! Transaction signed and ready to broadcast.
+        let tx = tx.expect("test");
         assert_eq!(314, tx.len() / 2);
 
         // check with:
@@ -441,7 +1370,15 @@ mod test {
             value: DUMMY_UTXO_AMOUNT_INPUT_1,
             script_pubkey: script_pubkey_1,
         };
-        let script_pubkey_2 = derive_address(desc, 1, Network::Bitcoin).script_pubkey();
+        // first internal address, matching the key origin of the second input
+        let script_pubkey_2 = desc
+            .clone()
+            .into_single_descriptors()
+            .expect("test")
+            .remove(1)
+            .at_derivation_index(0)
+            .expect("test")
+            .script_pubkey();
 
         let out_point_2 = OutPoint {
             txid: Txid::from_byte_array([0xFF; 32]),
@@ -540,4 +1477,1061 @@ mod test {
             .address(network)
             .expect("test")
     }
+
+    const COSIGNER_1: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const COSIGNER_2: &str =
+        "episode girl scorpion hope any pave carry rifle limit coffee review bus";
+
+    fn bip48_key(seed: &Seed, network: Network) -> String {
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        crate::derive::main(seed, params)
+            .expect("test")
+            .multisig
+            .expect("test")
+            .bip48_wsh
+    }
+
+    /// A temporary file containing the PSBT in base64
+    pub(crate) fn psbt_file(psbt: &Psbt) -> NamedTempFile {
+        let mut f = NamedTempFile::new().expect("test");
+        f.as_file_mut()
+            .write_all(psbt.to_string().as_bytes())
+            .expect("Unable to write data");
+        f
+    }
+
+    /// Parameters signing the given PSBT file, the other options are disabled
+    pub(crate) fn sign_params(psbt: &Path, network: Network) -> Params {
+        Params {
+            psbts: vec![psbt.to_path_buf()],
+            wallet: crate::wallet::WalletParams {
+                network,
+                max: 20,
+                accounts: 1,
+                descriptor: vec![],
+            },
+            require_prev_tx: None,
+            max_fee: None,
+            max_fee_rate: None,
+            max_fee_percent: None,
+            allow_any_sighash: false,
+            policy: None,
+            audit_log: None,
+            signed_outpoints: None,
+            allow_replace: false,
+            text: false,
+            sign_only: false,
+            binary_dir: None,
+        }
+    }
+
+    fn sign_psbt(seed: &Seed, psbt: &Psbt, network: Network) -> sign::Output {
+        sign_psbt_with_descriptors(seed, psbt, network, vec![])
+    }
+
+    fn sign_psbt_with_descriptors(
+        seed: &Seed,
+        psbt: &Psbt,
+        network: Network,
+        descriptor: Vec<String>,
+    ) -> sign::Output {
+        let f = psbt_file(psbt);
+        let mut params = sign_params(f.path(), network);
+        params.wallet.descriptor = descriptor;
+        sign::main(seed, params).expect("test").remove(0)
+    }
+
+    /// A PSBT spending from the first external address of every given descriptor to the receiver address
+    fn psbt_spending(descs: &[&Descriptor<DescriptorPublicKey>]) -> Psbt {
+        let definites: Vec<_> = descs
+            .iter()
+            .map(|desc| {
+                (*desc)
+                    .clone()
+                    .into_single_descriptors()
+                    .expect("test")
+                    .remove(0)
+                    .at_derivation_index(0)
+                    .expect("test")
+            })
+            .collect();
+        let prev_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: definites
+                .iter()
+                .map(|definite| TxOut {
+                    value: DUMMY_UTXO_AMOUNT_INPUT_1,
+                    script_pubkey: definite.script_pubkey(),
+                })
+                .collect(),
+        };
+        let unsigned_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: (0..definites.len() as u32)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint {
+                        txid: prev_tx.compute_txid(),
+                        vout,
+                    },
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: DUMMY_UTXO_AMOUNT_INPUT_1 * definites.len() as u64
+                    - Amount::from_sat(10_000),
+                script_pubkey: receivers_address().script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).expect("test");
+        for (i, definite) in definites.iter().enumerate() {
+            psbt.inputs[i].non_witness_utxo = Some(prev_tx.clone());
+            if definite.desc_type().segwit_version().is_some() {
+                psbt.inputs[i].witness_utxo = Some(prev_tx.output[i].clone());
+            }
+            psbt.update_input_with_descriptor(i, definite)
+                .expect("test");
+        }
+        psbt
+    }
+
+    #[test]
+    fn test_multisig_partial_signing() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let cosigner_1: Seed = COSIGNER_1.parse().expect("test");
+        let cosigner_2: Seed = COSIGNER_2.parse().expect("test");
+
+        let desc = format!(
+            "wsh(sortedmulti(2,{}/0/*,{}/0/*,{}/0/*))",
+            bip48_key(&seed, network),
+            bip48_key(&cosigner_1, network),
+            bip48_key(&cosigner_2, network)
+        );
+        let desc: Descriptor<DescriptorPublicKey> = desc.parse().expect("test");
+        let psbt = psbt_spending(&[&desc]);
+
+        let output = sign_psbt(&seed, &psbt, network);
+        assert_eq!(output.signatures_added, 1);
+        assert!(output.tx.is_none());
+        assert!(output.txid.is_none());
+        let psbt = output.psbt();
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+        assert!(psbt.inputs[0].final_script_witness.is_none());
+
+        let output = sign_psbt(&cosigner_1, &psbt, network);
+        assert_eq!(output.signatures_added, 1);
+        assert_ne!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.bal, 0);
+        let psbt = output.psbt();
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        assert!(psbt.inputs[0].final_script_witness.is_some());
+        let tx = output.tx().expect("finalized with 2 of 3 signatures");
+        assert_eq!(tx.input[0].witness.len(), 4); // empty, 2 signatures, witness script
+
+        // the multisig descriptor is used for ownership and balance
+        let psbt = psbt_spending(&[&desc]);
+        let output =
+            sign_psbt_with_descriptors(&cosigner_1, &psbt, network, vec![desc.to_string()]);
+        assert_eq!(output.signatures_added, 1);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.bal, -20_000_000);
+    }
+
+    #[test]
+    fn test_sign_only() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_MAINNET.parse().expect("test");
+        let psbt = psbt_spending(&[&desc]);
+
+        let f = psbt_file(&psbt);
+        let dir = tempfile::tempdir().expect("test");
+        let params = Params {
+            sign_only: true,
+            binary_dir: Some(dir.path().to_path_buf()),
+            ..sign_params(f.path(), Network::Bitcoin)
+        };
+        let output = sign::main(&seed, params).expect("test").remove(0);
+        assert_eq!(output.signatures_added, 1);
+        assert!(output.tx.is_none());
+        assert!(output.txid.is_none());
+
+        let signed = output.psbt();
+        assert!(signed.inputs[0].tap_key_sig.is_some());
+        assert!(signed.inputs[0].final_script_witness.is_none());
+        assert!(!signed.inputs[0].tap_key_origins.is_empty());
+
+        let stem = f.path().file_stem().expect("test").to_string_lossy();
+        let binary = std::fs::read(dir.path().join(format!("{stem}.signed.psbt"))).expect("test");
+        assert_eq!(Psbt::deserialize(&binary).expect("test"), signed);
+
+        let output = sign_psbt(&seed, &signed, Network::Bitcoin);
+        assert!(output.tx.is_some());
+    }
+
+    #[test]
+    fn test_collaborative_transaction() {
+        let network = Network::Bitcoin;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let other: Seed = COSIGNER_1.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let other_desc = crate::derive::main(&other, params)
+            .expect("test")
+            .singlesig
+            .expect("test")
+            .bip84_wpkh
+            .multipath;
+        let other_desc: Descriptor<DescriptorPublicKey> = other_desc.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_MAINNET.parse().expect("test");
+
+        for (descs, our_idx) in [([&other_desc, &desc], 1), ([&desc, &other_desc], 0)] {
+            let psbt = psbt_spending(&descs);
+            let other_idx = 1 - our_idx;
+
+            let output = sign_psbt(&seed, &psbt, network);
+            assert_eq!(output.signatures_added, 1);
+            assert_eq!(output.signed_inputs, vec![our_idx]);
+            assert_eq!(output.unsigned_inputs, vec![other_idx]);
+            assert!(output.tx.is_none());
+            let signed = output.psbt();
+            assert_eq!(signed.inputs[other_idx], psbt.inputs[other_idx]);
+            assert!(signed.inputs[our_idx].final_script_witness.is_some());
+
+            let output = sign_psbt(&other, &signed, network);
+            assert_eq!(output.signed_inputs, vec![other_idx]);
+            assert!(output.tx.is_some());
+            let final_fee_rate = output.fee_rate.expect("test");
+
+            // the input of the other participant is estimated with the worst case of p2wpkh
+            let estimate = |psbt: &Psbt, max_fee_rate| {
+                let f = psbt_file(psbt);
+                let params = Params {
+                    max_fee_rate: Some(max_fee_rate),
+                    ..sign_params(f.path(), network)
+                };
+                sign::main(&seed, params).map(|mut o| o.remove(0))
+            };
+            let output = estimate(&psbt, 1000.0).expect("test");
+            let estimated = output.estimated_fee_rate.expect("test");
+            assert!(estimated <= final_fee_rate, "{estimated} {final_fee_rate}");
+            assert!(output.unestimated_inputs.is_empty());
+
+            // a p2wsh is estimated with its witness script, unknown without
+            let mut psbt = psbt;
+            let pk = psbt.inputs[other_idx]
+                .bip32_derivation
+                .keys()
+                .next()
+                .expect("test");
+            let witness_script = bitcoin::script::Builder::new()
+                .push_key(&bitcoin::PublicKey::new(*pk))
+                .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+                .into_script();
+            let mut prev_tx = psbt.inputs[other_idx]
+                .non_witness_utxo
+                .clone()
+                .expect("test");
+            prev_tx.output[other_idx].script_pubkey =
+                ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+            for (input, txin) in psbt.inputs.iter_mut().zip(&mut psbt.unsigned_tx.input) {
+                txin.previous_output.txid = prev_tx.compute_txid();
+                input.non_witness_utxo = Some(prev_tx.clone());
+            }
+            psbt.inputs[other_idx].witness_utxo = Some(prev_tx.output[other_idx].clone());
+            psbt.inputs[other_idx].witness_script = Some(witness_script);
+            let output = estimate(&psbt, 1000.0).expect("test");
+            assert!(output.unestimated_inputs.is_empty());
+            let estimated = output.estimated_fee_rate.expect("test");
+
+            psbt.inputs[other_idx].witness_script = None;
+            let output = estimate(&psbt, 1000.0).expect("test");
+            assert_eq!(output.unestimated_inputs, vec![other_idx]);
+            assert!(output.estimated_fee_rate.expect("test") > estimated);
+            let err = estimate(&psbt, 10.0).expect_err("test");
+            assert!(
+                format!("{err:?}").contains("unknown satisfaction"),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_finalize_standard_descriptors() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let singlesig = crate::derive::main(&seed, params)
+            .expect("test")
+            .singlesig
+            .expect("test");
+        for descs in [
+            singlesig.bip44_pkh,
+            singlesig.bip49_shwpkh,
+            singlesig.bip84_wpkh,
+            singlesig.bip86_tr,
+        ] {
+            let desc: Descriptor<DescriptorPublicKey> = descs.multipath.parse().expect("test");
+            let psbt = psbt_spending(&[&desc, &desc]);
+            let output = sign_psbt(&seed, &psbt, network);
+            assert_eq!(output.signed_inputs, vec![0, 1], "{desc}");
+            assert!(output.tx.is_some(), "{desc}");
+
+            // the key origin doesn't match the spent script, the signature is invalid
+            let mut psbt = psbt;
+            let script_pubkey = derive_address(&desc, 1, network).script_pubkey();
+            let mut prev_tx = psbt.inputs[1].non_witness_utxo.clone().expect("test");
+            prev_tx.output[1].script_pubkey = script_pubkey.clone();
+            psbt.unsigned_tx.input[1].previous_output.txid = prev_tx.compute_txid();
+            psbt.inputs[1].non_witness_utxo = Some(prev_tx);
+            if let Some(witness_utxo) = psbt.inputs[1].witness_utxo.as_mut() {
+                witness_utxo.script_pubkey = script_pubkey;
+            }
+            let f = psbt_file(&psbt);
+            let params = sign_params(f.path(), network);
+            assert!(sign::main(&seed, params).is_err(), "{desc}");
+        }
+    }
+
+    /// Unspendable internal key from bip341
+    const NUMS: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+    fn custom_key(seed: &Seed, path: &str, network: Network) -> String {
+        let params = crate::derive::Params {
+            path: Some(path.parse().expect("test")),
+            network,
+            account: 0,
+        };
+        crate::derive::main(seed, params)
+            .expect("test")
+            .custom
+            .expect("test")
+    }
+
+    #[test]
+    fn test_taproot_script_path() {
+        let network = Network::Testnet;
+        let heir: Seed = CODEX_32.parse().expect("test");
+        let owner: Seed = COSIGNER_1.parse().expect("test");
+        let heir_key = custom_key(&heir, "86h/1h/1h", network);
+        let owner_key = custom_key(&owner, "86h/1h/0h", network);
+
+        for internal in [NUMS.to_string(), format!("{owner_key}/0/*")] {
+            let desc = format!("tr({internal},and_v(v:pk({heir_key}/0/*),older(10)))");
+            let desc: Descriptor<DescriptorPublicKey> = desc.parse().expect("test");
+            let psbt = psbt_spending(&[&desc]);
+
+            // before the timelock our signature is added but the input can't be finalized
+            let output = sign_psbt(&heir, &psbt, network);
+            assert_eq!(output.signatures_added, 1, "{desc}");
+            assert!(output.tx.is_none());
+            let signed = output.psbt();
+            assert_eq!(signed.inputs[0].tap_script_sigs.len(), 1);
+            assert!(signed.inputs[0].tap_key_sig.is_none());
+
+            let mut psbt = psbt;
+            psbt.unsigned_tx.input[0].sequence = Sequence::from_height(10);
+            let output = sign_psbt(&heir, &psbt, network);
+            assert_eq!(output.signatures_added, 1);
+            let tx = output.tx().expect("finalized with the script path");
+            // signature, script and control block
+            assert_eq!(tx.input[0].witness.len(), 3);
+
+            // leaf hashes missing in the key origins
+            for (leaf_hashes, _) in psbt.inputs[0].tap_key_origins.values_mut() {
+                leaf_hashes.clear();
+            }
+            let output = sign_psbt(&heir, &psbt, network);
+            assert_eq!(output.signatures_added, 1);
+            assert!(output.tx.is_some());
+        }
+    }
+
+    #[test]
+    fn test_inheritance_descriptor() {
+        let network = Network::Testnet;
+        let secp = Secp256k1::new();
+        let owner: Seed = CODEX_32.parse().expect("test");
+        let heir: Seed = COSIGNER_1.parse().expect("test");
+        let owner_key = format!("{}/<0;1>/*", bip48_key(&owner, network));
+        let heir_key = format!("{}/<0;1>/*", bip48_key(&heir, network));
+
+        let owner_template = format!("wsh(or_d(pk(@me),and_v(v:pk({heir_key}),older(10))))");
+        let heir_template = format!("wsh(or_d(pk({owner_key}),and_v(v:pk(@me),older(10))))");
+        let desc = crate::import::expand_descriptor(&owner, network, &secp, 0, &owner_template)
+            .expect("test");
+        let heir_desc = crate::import::expand_descriptor(&heir, network, &secp, 0, &heir_template)
+            .expect("test");
+        assert_eq!(desc, heir_desc);
+        let psbt = psbt_spending(&[&desc]);
+
+        // standard descriptors don't know the input
+        let output = sign_psbt(&owner, &psbt, network);
+        assert_ne!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.bal, 0);
+
+        let output = sign_psbt_with_descriptors(&owner, &psbt, network, vec![owner_template]);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.bal, -20_000_000);
+        assert_eq!(output.signed_inputs, vec![0]);
+        let tx = output.tx().expect("owner spends without timelock");
+        assert_eq!(tx.input[0].witness.len(), 2); // signature, witness script
+
+        // the heir signs but can finalize only after the timelock
+        let heir_descriptor = vec![heir_template];
+        let output = sign_psbt_with_descriptors(&heir, &psbt, network, heir_descriptor.clone());
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.signed_inputs, vec![0]);
+        assert!(output.tx.is_none());
+
+        let mut psbt = psbt;
+        psbt.unsigned_tx.input[0].sequence = Sequence::from_height(10);
+        let output = sign_psbt_with_descriptors(&heir, &psbt, network, heir_descriptor);
+        let tx = output.tx().expect("heir spends after the timelock");
+        assert_eq!(tx.input[0].witness.len(), 3); // signature, owner dissatisfaction, witness script
+    }
+
+    #[test]
+    fn test_change_verification() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let cosigner: Seed = COSIGNER_1.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let singlesig = crate::derive::main(&seed, params)
+            .expect("test")
+            .singlesig
+            .expect("test");
+        let multisig = format!(
+            "wsh(multi(1,{}/1/*,{}/1/*))",
+            bip48_key(&cosigner, network),
+            bip48_key(&seed, network)
+        );
+        let multisig: Descriptor<DescriptorPublicKey> = multisig.parse().expect("test");
+
+        for descs in [
+            singlesig.bip44_pkh,
+            singlesig.bip84_wpkh,
+            singlesig.bip86_tr,
+        ] {
+            let desc: Descriptor<DescriptorPublicKey> = descs.multipath.parse().expect("test");
+            let internal = desc
+                .clone()
+                .into_single_descriptors()
+                .expect("test")
+                .remove(1);
+            let mut psbt = psbt_spending(&[&desc]);
+
+            // change beyond the precomputed addresses, a fake change claiming our key origin but
+            // paying someone else, and a multisig with a cosigner
+            let change = internal.at_derivation_index(1500).expect("test");
+            let fake = internal.at_derivation_index(3).expect("test");
+            let shared = multisig.at_derivation_index(0).expect("test");
+            for definite in [&change, &fake, &shared] {
+                psbt.unsigned_tx.output.push(TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: definite.script_pubkey(),
+                });
+                psbt.outputs.push(Default::default());
+                let output_idx = psbt.outputs.len() - 1;
+                psbt.update_output_with_descriptor(output_idx, definite)
+                    .expect("test");
+            }
+            psbt.unsigned_tx.output[2].script_pubkey = receivers_address().script_pubkey();
+            psbt.unsigned_tx.output[0].value -= Amount::from_sat(3_000);
+
+            let output = sign_psbt(&seed, &psbt, network);
+            assert_ne!(output.outputs[0].ownership, Ownership::Ours, "{desc}");
+            assert_eq!(output.outputs[1].ownership, Ownership::Ours, "{desc}");
+            assert!(output.outputs[2].ownership == Ownership::Fake, "{desc}");
+            assert_ne!(output.outputs[3].ownership, Ownership::Ours, "{desc}");
+            assert_eq!(output.fake_outputs, vec![2], "{desc}");
+            assert_eq!(output.bal, -19_999_000, "{desc}");
+
+            // the key origin doesn't derive the claimed key
+            psbt.unsigned_tx.output[2].script_pubkey = fake.script_pubkey();
+            let change = &mut psbt.outputs[1];
+            let origins = change
+                .bip32_derivation
+                .values_mut()
+                .chain(change.tap_key_origins.values_mut().map(|(_, o)| o));
+            for (_, path) in origins {
+                *path = path.child(ChildNumber::from_normal_idx(0).expect("test"));
+            }
+            let output = sign_psbt(&seed, &psbt, network);
+            assert_eq!(output.fake_outputs, vec![1], "{desc}");
+            assert_eq!(output.bal, -19_999_000, "{desc}");
+        }
+    }
+
+    #[test]
+    fn test_change_outside_descriptors() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let internal = |account| {
+            let params = crate::derive::Params {
+                path: None,
+                network,
+                account,
+            };
+            let singlesig = crate::derive::main(&seed, params)
+                .expect("test")
+                .singlesig
+                .expect("test");
+            let desc: Descriptor<DescriptorPublicKey> =
+                singlesig.bip84_wpkh.multipath.parse().expect("test");
+            desc.into_single_descriptors().expect("test").remove(1)
+        };
+        let desc = internal(0);
+        let mut psbt = psbt_spending(&[&desc]);
+
+        // a change of an account not scanned, m/84'/1'/7777'/1/5, and a p2pkh of a bip84 key
+        let unscanned = internal(7777).at_derivation_index(5).expect("test");
+        let bip84 = desc.at_derivation_index(5).expect("test");
+        for definite in [&unscanned, &bip84] {
+            psbt.unsigned_tx.output.push(TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: definite.script_pubkey(),
+            });
+            psbt.outputs.push(Default::default());
+            let output_idx = psbt.outputs.len() - 1;
+            psbt.update_output_with_descriptor(output_idx, definite)
+                .expect("test");
+        }
+        let pk = psbt.outputs[2]
+            .bip32_derivation
+            .keys()
+            .next()
+            .expect("test");
+        let p2pkh = ScriptBuf::new_p2pkh(&bitcoin::CompressedPublicKey(*pk).pubkey_hash());
+        psbt.unsigned_tx.output[2].script_pubkey = p2pkh;
+        psbt.unsigned_tx.output[0].value -= Amount::from_sat(2_000);
+
+        let output = sign_psbt(&seed, &psbt, network);
+        assert_eq!(output.outputs[1].ownership, Ownership::Fake);
+        assert_eq!(output.outputs[2].ownership, Ownership::Fake);
+        assert_eq!(output.fake_outputs, vec![1, 2]);
+        assert_eq!(output.bal, -20_000_000);
+    }
+
+    #[test]
+    fn test_ownership_from_key_origins() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let cosigner: Seed = COSIGNER_1.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let multisig = format!(
+            "wsh(multi(1,{}/<0;1>/*,{}/<0;1>/*))",
+            bip48_key(&cosigner, network),
+            bip48_key(&seed, network)
+        );
+        let sign = |psbt: &Psbt, max: u32, descriptor: Vec<String>| {
+            let f = psbt_file(psbt);
+            let mut params = sign_params(f.path(), network);
+            params.wallet.max = max;
+            params.wallet.descriptor = descriptor;
+            sign::main(&seed, params).expect("test").remove(0)
+        };
+
+        // key origins are enough, addresses are not generated
+        let psbt = psbt_spending(&[&desc]);
+        let output = sign(&psbt, 0, vec![]);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert!(output.tx.is_some());
+
+        // without key origins ownership is checked against the generated addresses
+        let mut stripped = psbt.clone();
+        stripped.inputs[0].tap_key_origins.clear();
+        let output = sign(&stripped, 0, vec![]);
+        assert_ne!(output.inputs[0].ownership, Ownership::Ours);
+        let output = sign(&stripped, 1, vec![]);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+
+        // the index of the key origin is used to derive the passed descriptors
+        let multisig_desc: Descriptor<DescriptorPublicKey> = multisig.parse().expect("test");
+        let mut psbt = psbt_spending(&[&multisig_desc]);
+        let change = multisig_desc
+            .into_single_descriptors()
+            .expect("test")
+            .remove(1)
+            .at_derivation_index(1500)
+            .expect("test");
+        psbt.unsigned_tx.output.push(TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: change.script_pubkey(),
+        });
+        psbt.unsigned_tx.output[0].value -= Amount::from_sat(1_000);
+        psbt.outputs.push(Default::default());
+        psbt.update_output_with_descriptor(1, &change)
+            .expect("test");
+        let output = sign(&psbt, 0, vec![]);
+        assert_ne!(output.inputs[0].ownership, Ownership::Ours);
+        assert_ne!(output.outputs[1].ownership, Ownership::Ours);
+        assert!(output.fake_outputs.is_empty());
+        let output = sign(&psbt, 0, vec![multisig]);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.outputs[1].ownership, Ownership::Ours);
+        assert_eq!(output.bal, -19_999_000);
+    }
+
+    #[test]
+    fn test_change_without_key_origins() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let sign = |psbt: &Psbt, max: u32| {
+            let f = psbt_file(psbt);
+            let mut params = sign_params(f.path(), network);
+            params.wallet.max = max;
+            sign::main(&seed, params).expect("test").remove(0)
+        };
+
+        // the input has key origins, the change doesn't and it's foreign whatever the max
+        let mut psbt = psbt_spending(&[&desc]);
+        let change = desc
+            .clone()
+            .into_single_descriptors()
+            .expect("test")
+            .remove(1)
+            .at_derivation_index(2)
+            .expect("test");
+        psbt.unsigned_tx.output.push(TxOut {
+            value: Amount::from_sat(1_000_000),
+            script_pubkey: change.script_pubkey(),
+        });
+        psbt.unsigned_tx.output[0].value -= Amount::from_sat(1_000_000);
+        psbt.outputs.push(Default::default());
+
+        let output = sign(&psbt, 2);
+        assert_eq!(output.inputs[0].ownership, Ownership::Ours);
+        assert_eq!(output.outputs[1].ownership, Ownership::Foreign);
+
+        let output = sign(&psbt, 3);
+        assert_eq!(output.outputs[1].ownership, Ownership::Foreign);
+        assert_eq!(output.bal, -20_000_000);
+    }
+
+    #[test]
+    fn test_prev_tx_validation() {
+        let network = Network::Bitcoin;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let singlesig = crate::derive::main(&seed, params)
+            .expect("test")
+            .singlesig
+            .expect("test");
+        let wpkh: Descriptor<DescriptorPublicKey> =
+            singlesig.bip84_wpkh.multipath.parse().expect("test");
+        let tr: Descriptor<DescriptorPublicKey> =
+            singlesig.bip86_tr.multipath.parse().expect("test");
+        let sign = |psbt: &Psbt, require_prev_tx: Option<bool>| {
+            let f = psbt_file(psbt);
+            let params = Params {
+                require_prev_tx,
+                ..sign_params(f.path(), network)
+            };
+            sign::main(&seed, params)
+        };
+
+        let psbt = psbt_spending(&[&wpkh, &tr]);
+        assert!(sign(&psbt, None).expect("test")[0].tx.is_some());
+
+        // on mainnet the previous transaction is required for segwit v0 but not for taproot
+        let mut without_prev_tx = psbt.clone();
+        without_prev_tx.inputs[1].non_witness_utxo = None;
+        assert!(sign(&without_prev_tx, None).is_ok());
+        without_prev_tx.inputs[0].non_witness_utxo = None;
+        assert!(sign(&without_prev_tx, None).is_err());
+        assert!(sign(&without_prev_tx, Some(false)).is_ok());
+
+        // a lower amount in the witness utxo to hide the fee
+        let mut fake_amount = psbt.clone();
+        fake_amount.inputs[0]
+            .witness_utxo
+            .as_mut()
+            .expect("test")
+            .value = Amount::from_sat(1_000);
+        assert!(sign(&fake_amount, Some(false)).is_err());
+
+        // the previous transaction doesn't match the outpoint
+        let mut wrong_prev_tx = psbt;
+        wrong_prev_tx.inputs[0]
+            .non_witness_utxo
+            .as_mut()
+            .expect("test")
+            .lock_time = absolute::LockTime::from_consensus(1);
+        assert!(sign(&wrong_prev_tx, Some(false)).is_err());
+    }
+
+    #[test]
+    fn test_fee_guard_rails() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let psbt = psbt_spending(&[&desc]);
+        let f = psbt_file(&psbt);
+        let sign = |max_fee, max_fee_rate, max_fee_percent| {
+            let params = Params {
+                max_fee,
+                max_fee_rate,
+                max_fee_percent,
+                ..sign_params(f.path(), network)
+            };
+            sign::main(&seed, params)
+        };
+
+        let output = sign(None, None, None).expect("test").remove(0);
+        let tx = output.tx().expect("test");
+        assert_eq!(output.fee, 10_000);
+        assert_eq!(output.weight, Some(tx.weight().to_wu()));
+        assert_eq!(output.vsize, Some(111));
+        assert_eq!(output.fee_rate, Some(10_000.0 / 111.0));
+
+        // the fee is 10000 sat, 90.09 sat/vB and 0.05% of the amount sent
+        assert!(sign(Some(9_999), None, None).is_err());
+        assert!(sign(Some(10_000), None, None).is_ok());
+        assert!(sign(None, Some(90.0), None).is_err());
+        assert!(sign(None, Some(91.0), None).is_ok());
+        assert!(sign(None, None, Some(0.04)).is_err());
+        assert!(sign(None, None, Some(0.06)).is_ok());
+
+        // without finalization the fee rate is estimated before signing
+        let sign_only = |max_fee_rate| {
+            let params = Params {
+                max_fee_rate: Some(max_fee_rate),
+                sign_only: true,
+                ..sign_params(f.path(), network)
+            };
+            sign::main(&seed, params)
+        };
+        assert!(sign_only(89.0).is_err());
+        assert!(sign_only(91.0).is_ok());
+    }
+
+    #[test]
+    fn test_structured_output() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let mut psbt = psbt_spending(&[&desc]);
+        psbt.unsigned_tx.input[0].sequence = Sequence::from_height(10);
+        let output = sign_psbt(&seed, &psbt, network);
+
+        let input = &output.inputs[0];
+        assert_eq!(input.outpoint, psbt.unsigned_tx.input[0].previous_output);
+        assert_eq!(input.amount, 20_000_000);
+        assert_eq!(
+            input.address,
+            Some(derive_address(&desc, 0, network).to_string())
+        );
+        assert_eq!(input.ownership, Ownership::Ours);
+        assert_eq!(input.desc_type.as_deref(), Some("Tr"));
+        assert_eq!(input.path, Some("86'/1'/0'/0/0".parse().expect("test")));
+        assert_eq!(input.sequence, 10);
+        assert_eq!(input.relative_timelock.as_deref(), Some("10 blocks"));
+
+        let out = &output.outputs[0];
+        assert_eq!(out.script_pubkey, receivers_address().script_pubkey());
+        assert_eq!(out.ownership, Ownership::Foreign);
+        assert!(out.desc_type.is_none());
+        assert_eq!(output.lock_time, 0);
+
+        let json = serde_json::to_value(&output).expect("test");
+        assert_eq!(json["inputs"][0]["ownership"], "ours");
+        assert_eq!(json["inputs"][0]["path"], "86'/1'/0'/0/0");
+        assert_eq!(json["bal"], -20_000_000);
+
+        let text = output.to_string();
+        let address = input.address.as_ref().expect("test");
+        assert!(text.contains(&format!("  20000000:{address} mine")));
+        assert!(text.contains("bal: -20000000 sat"));
+    }
+
+    #[test]
+    fn test_non_address_outputs() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_TESTNET.parse().expect("test");
+        let mut psbt = psbt_spending(&[&desc]);
+
+        let op_return = ScriptBuf::new_op_return(b"hello");
+        let p2a = ScriptBuf::from_bytes(vec![0x51, 0x02, 0x4e, 0x73]);
+        let pk: bitcoin::PublicKey = format!("02{NUMS}").parse().expect("test");
+        let bare = bitcoin::script::Builder::new()
+            .push_int(1)
+            .push_key(&pk)
+            .push_int(1)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        for (script_pubkey, value) in [(op_return, 0), (p2a, 240), (bare, 1_000)] {
+            psbt.unsigned_tx.output.push(TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey,
+            });
+            psbt.outputs.push(Default::default());
+        }
+        psbt.unsigned_tx.output[0].value -= Amount::from_sat(1_240);
+
+        let output = sign_psbt(&seed, &psbt, network);
+        assert!(output.tx.is_some());
+        assert_eq!(output.fee, 10_000);
+        let types: Vec<_> = output
+            .outputs
+            .iter()
+            .map(|o| o.script_type.as_str())
+            .collect();
+        assert_eq!(types, vec!["p2tr", "op_return", "p2a", "multisig"]);
+
+        assert!(output.outputs[1].address.is_none());
+        assert_eq!(output.outputs[1].op_return.as_deref(), Some("68656c6c6f"));
+        assert!(output.outputs[2].address.is_some());
+        assert!(output.outputs[2].op_return.is_none());
+        assert!(output.outputs[3].address.is_none());
+
+        let text = output.to_string();
+        assert!(text.contains("         0:op_return:68656c6c6f"));
+        assert!(text.contains("      1000:multisig:5121"));
+    }
+
+    #[test]
+    fn test_sighash_policy() {
+        let network = Network::Testnet;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let singlesig = crate::derive::main(&seed, params)
+            .expect("test")
+            .singlesig
+            .expect("test");
+        let wpkh: Descriptor<DescriptorPublicKey> =
+            singlesig.bip84_wpkh.multipath.parse().expect("test");
+        let tr: Descriptor<DescriptorPublicKey> =
+            singlesig.bip86_tr.multipath.parse().expect("test");
+        let sign = |psbt: &Psbt, allow_any_sighash| {
+            let f = psbt_file(psbt);
+            let params = Params {
+                allow_any_sighash,
+                ..sign_params(f.path(), network)
+            };
+            sign::main(&seed, params).map(|mut o| o.remove(0))
+        };
+
+        let mut psbt = psbt_spending(&[&wpkh, &tr]);
+        let output = sign(&psbt, false).expect("test");
+        assert_eq!(output.inputs[0].sighash, "SIGHASH_ALL");
+        assert_eq!(output.inputs[1].sighash, "SIGHASH_DEFAULT");
+
+        psbt.inputs[0].sighash_type = Some(EcdsaSighashType::All.into());
+        psbt.inputs[1].sighash_type = Some(TapSighashType::All.into());
+        let output = sign(&psbt, false).expect("test");
+        assert_eq!(output.inputs[1].sighash, "SIGHASH_ALL");
+
+        for (idx, sighash) in [
+            (0, EcdsaSighashType::SinglePlusAnyoneCanPay.into()),
+            (1, TapSighashType::NonePlusAnyoneCanPay.into()),
+        ] {
+            let mut psbt = psbt.clone();
+            psbt.inputs[idx].sighash_type = Some(sighash);
+            assert!(sign(&psbt, false).is_err());
+            let output = sign(&psbt, true).expect("test");
+            assert_eq!(output.signed_inputs, vec![0, 1]);
+            assert_eq!(output.inputs[idx].sighash, sighash.to_string());
+        }
+    }
+
+    #[test]
+    fn test_spending_policy() {
+        let network = Network::Bitcoin;
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_MAINNET.parse().expect("test");
+        let cosigner: Seed = COSIGNER_1.parse().expect("test");
+        let params = crate::derive::Params {
+            path: None,
+            network,
+            account: 0,
+        };
+        let cosigner_desc = crate::derive::main(&cosigner, params)
+            .expect("test")
+            .singlesig
+            .expect("test")
+            .bip84_wpkh
+            .multipath;
+        let cosigner_desc: Descriptor<DescriptorPublicKey> = cosigner_desc.parse().expect("test");
+        let receiver = derive_address(&cosigner_desc, 3, network);
+        let other = derive_address(&desc, 5, network);
+
+        let mut psbt = psbt_spending(&[&desc]);
+        psbt.unsigned_tx.output[0].script_pubkey = receiver.script_pubkey();
+        psbt.unsigned_tx.lock_time = absolute::LockTime::from_height(850_000).expect("test");
+        let f = psbt_file(&psbt);
+        let sign = |policy: &str| {
+            let mut policy_file = NamedTempFile::new().expect("test");
+            policy_file
+                .as_file_mut()
+                .write_all(policy.as_bytes())
+                .expect("Unable to write data");
+            let params = Params {
+                policy: Some(policy_file.path().to_path_buf()),
+                ..sign_params(f.path(), network)
+            };
+            sign::main(&seed, params)
+        };
+        assert!(sign("{}").is_ok());
+        assert!(sign(&format!(r#"{{"allowed_addresses": ["{receiver}"]}}"#)).is_ok());
+        assert!(sign(&format!(
+            r#"{{"allowed_descriptors": ["{cosigner_desc}"]}}"#
+        ))
+        .is_ok());
+
+        let cases = [
+            format!(r#"{{"allowed_addresses": ["{other}"]}}"#),
+            format!(r#"{{"allowed_descriptors": ["{desc}"]}}"#),
+            r#"{"max_sent": 19989999}"#.to_string(),
+            r#"{"max_fee": 9999}"#.to_string(),
+            r#"{"allowed_lock_times": [{"min": 0, "max": 0}]}"#.to_string(),
+            r#"{"max_fees": 10000}"#.to_string(),
+        ];
+        for policy in cases {
+            let err = sign(&policy).expect_err("test");
+            assert!(matches!(err, crate::Error::Policy(_)), "{policy} {err:?}");
+        }
+
+        let policy = r#"{"max_sent": 19990000, "max_fee": 10000, "allowed_lock_times": [{"min": 0, "max": 0}, {"min": 840000, "max": 860000}]}"#;
+        let output = sign(policy).expect("test").remove(0);
+        assert!(output.tx.is_some());
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_MAINNET.parse().expect("test");
+        let psbt = psbt_spending(&[&desc]);
+
+        let f = psbt_file(&psbt);
+        let dir = tempfile::tempdir().expect("test");
+        let log = dir.path().join("audit.log");
+        let sign = || {
+            let params = Params {
+                audit_log: Some(log.clone()),
+                ..sign_params(f.path(), Network::Bitcoin)
+            };
+            sign::main(&seed, params).expect("test").remove(0)
+        };
+        let verify = |head| {
+            let params = crate::verify_log::Params {
+                log: log.clone(),
+                head,
+            };
+            crate::verify_log::main(params)
+        };
+
+        let output = sign();
+        let first = verify(None).expect("test");
+        assert_eq!(first.entries, 1);
+        sign();
+        let second = verify(Some(first.head)).expect("test");
+        assert_eq!(second.entries, 2);
+
+        let content = std::fs::read_to_string(&log).expect("test");
+        let entry: crate::verify_log::LogEntry =
+            serde_json::from_str(content.lines().next().expect("test")).expect("test");
+        assert_eq!(entry.txid, psbt.unsigned_tx.compute_txid());
+        assert_eq!(entry.fee, output.fee);
+        assert_eq!(entry.bal, output.bal);
+        assert!(entry.inputs[0].ends_with(" mine"));
+        assert_eq!(entry.final_txid, output.txid);
+        let signed = format!("0:{}", output.inputs[0].sighash);
+        assert_eq!(entry.signed_inputs, vec![signed.clone()]);
+
+        // signatures not finalized leave a trace too
+        let params = Params {
+            audit_log: Some(log.clone()),
+            sign_only: true,
+            ..sign_params(f.path(), Network::Bitcoin)
+        };
+        sign::main(&seed, params).expect("test");
+        let third = verify(Some(second.head)).expect("test");
+        assert_eq!(third.entries, 3);
+        let content = std::fs::read_to_string(&log).expect("test");
+        let entry: crate::verify_log::LogEntry =
+            serde_json::from_str(content.lines().last().expect("test")).expect("test");
+        assert_eq!(entry.final_txid, None);
+        assert_eq!(entry.signed_inputs, vec![signed]);
+
+        // the last entries removed are detected only with the previously recorded head
+        let truncated: String = content.lines().take(1).map(|l| format!("{l}\n")).collect();
+        std::fs::write(&log, truncated).expect("test");
+        assert!(verify(None).is_ok());
+        assert!(verify(Some(third.head)).is_err());
+
+        // a tampered log is not extended
+        std::fs::write(&log, content.replacen("\"fee\"", "\"fee\":0,\"x\"", 1)).expect("test");
+        assert!(verify(None).is_err());
+        let params = Params {
+            audit_log: Some(log.clone()),
+            ..sign_params(f.path(), Network::Bitcoin)
+        };
+        assert!(sign::main(&seed, params).is_err());
+    }
+
+    #[test]
+    fn test_double_signing() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let desc: Descriptor<DescriptorPublicKey> = DESCRIPTOR_MAINNET.parse().expect("test");
+        let psbt = psbt_spending(&[&desc]);
+        let mut replacement = psbt.clone();
+        replacement.unsigned_tx.output[0].value -= Amount::from_sat(1_000);
+
+        let dir = tempfile::tempdir().expect("test");
+        let record = dir.path().join("signed_outpoints.json");
+        let sign = |psbt: &Psbt, allow_replace: bool| {
+            let f = psbt_file(psbt);
+            let params = Params {
+                signed_outpoints: Some(record.clone()),
+                allow_replace,
+                ..sign_params(f.path(), Network::Bitcoin)
+            };
+            sign::main(&seed, params)
+        };
+
+        let output = sign(&psbt, false).expect("test");
+        assert!(output[0].replaced_txids.is_empty());
+        // signing the same transaction again is fine
+        assert!(sign(&psbt, false).is_ok());
+
+        let txid = psbt.unsigned_tx.compute_txid();
+        let err = sign(&replacement, false).expect_err("test");
+        assert!(format!("{err:?}").contains(&txid.to_string()), "{err:?}");
+
+        let output = sign(&replacement, true).expect("test").remove(0);
+        assert_eq!(output.replaced_txids, vec![txid]);
+
+        // the record now points to the replacement
+        let err = sign(&psbt, false).expect_err("test");
+        let replacement_txid = replacement.unsigned_tx.compute_txid();
+        assert!(format!("{err:?}").contains(&replacement_txid.to_string()));
+    }
 }
@@ -0,0 +1,122 @@
+use crate::Error;
+use bitcoin::{OutPoint, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Record of the outpoints spent by the transactions we signed, kept in a json file, used to
+/// detect conflicting spends of the same outpoint.
+///
+/// Every outpoint maps to the txid of the unsigned transaction spending it, which commits to
+/// everything but the signatures. It's the final txid except for transactions with legacy
+/// inputs, whose script sigs are part of the txid.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SignedOutpoints(BTreeMap<OutPoint, Txid>);
+
+impl SignedOutpoints {
+    /// Load the record, empty if the file doesn't exist yet
+    pub(crate) fn load(path: &Path) -> Result<SignedOutpoints, Error> {
+        match std::fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(file).map_err(|e| {
+                Error::String(format!(
+                    "invalid signed outpoints file {}: {e}",
+                    path.display()
+                ))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(SignedOutpoints::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Unsigned txids of the transactions we signed spending some of the inputs of the unsigned
+    /// `tx`, other than `tx` itself
+    pub(crate) fn conflicts(&self, tx: &Transaction) -> Vec<Txid> {
+        let unsigned_txid = tx.compute_txid();
+        let mut txids: Vec<_> = tx
+            .input
+            .iter()
+            .filter_map(|i| self.0.get(&i.previous_output))
+            .filter(|txid| **txid != unsigned_txid)
+            .copied()
+            .collect();
+        txids.sort();
+        txids.dedup();
+        txids
+    }
+
+    /// Record the inputs of the unsigned `tx` at the given indexes as signed by `tx`
+    pub(crate) fn insert(&mut self, tx: &Transaction, input_indexes: &[usize]) {
+        let txid = tx.compute_txid();
+        for input_idx in input_indexes {
+            let outpoint = tx.input[*input_idx].previous_output;
+            self.0.insert(outpoint, txid);
+        }
+    }
+
+    /// Write the record in a temporary file of the same directory, renamed over the previous one
+    /// once synced, so that the previous record survives a crash or unplugged media
+    pub(crate) fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).expect("doesn't contain non-string key");
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SignedOutpoints;
+    use bitcoin::{absolute, transaction, Amount, ScriptBuf, Transaction, TxIn, TxOut};
+
+    #[test]
+    fn test_conflicts() {
+        let input = |vout| TxIn {
+            previous_output: format!(
+                "77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754:{vout}"
+            )
+            .parse()
+            .expect("test"),
+            ..Default::default()
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![input(0), input(1)],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut record = SignedOutpoints::default();
+        record.insert(&tx, &[0]);
+
+        // re-signing the same transaction
+        assert!(record.conflicts(&tx).is_empty());
+
+        let mut other = tx.clone();
+        other.output[0].value = Amount::from_sat(900);
+        assert_eq!(record.conflicts(&other), vec![tx.compute_txid()]);
+
+        // same outputs but something else changed
+        let mut other_lock_time = tx.clone();
+        other_lock_time.lock_time = absolute::LockTime::from_consensus(1);
+        assert_eq!(record.conflicts(&other_lock_time), vec![tx.compute_txid()]);
+
+        // the other input wasn't signed
+        other.input.remove(0);
+        assert!(record.conflicts(&other).is_empty());
+
+        let dir = tempfile::tempdir().expect("test");
+        let path = dir.path().join("signed_outpoints.json");
+        record.save(&path).expect("test");
+        record.save(&path).expect("test");
+        let parsed = SignedOutpoints::load(&path).expect("test");
+        assert_eq!(parsed.0.len(), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).expect("test").count(), 1);
+    }
+}
@@ -8,12 +8,15 @@ use miniscript::descriptor::DescriptorType;
 use miniscript::{Descriptor, DescriptorPublicKey};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ops::Range;
 
-use crate::import::compute_descriptors;
+use crate::import::{compute_descriptors, expand_descriptor};
+use crate::wallet::WalletParams;
 use crate::Error;
 use crate::Seed;
 
 /// Given a seed and an address tell if we can spend from it from standard descriptors (bip 44,49,84,86)
+/// or from the given descriptors
 #[derive(Parser, Debug)]
 #[command(author, version)]
 pub struct Params {
@@ -21,13 +24,8 @@ pub struct Params {
     #[clap(short, long)]
     pub address: Address<NetworkUnchecked>,
 
-    /// Generated addresses up to this number
-    #[clap(short, long, default_value_t = 1000)]
-    pub max: u32,
-
-    /// Bitcoin Network. bitcoin,testnet,signet are possible values
-    #[clap(short, long, env)]
-    pub network: Network,
+    #[command(flatten)]
+    pub wallet: WalletParams,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,13 +41,18 @@ pub struct Output {
 pub fn main(seed: &Seed, params: Params) -> Result<Output, Error> {
     let Params {
         address,
-        max,
-        network,
+        wallet:
+            WalletParams {
+                network,
+                max,
+                accounts,
+                descriptor,
+            },
     } = params;
     let address = address.require_network(network)?;
 
     let secp = Secp256k1::new();
-    let descriptors = compute_finite_descriptors(seed, network, &secp)?;
+    let descriptors = compute_finite_descriptors(seed, network, &secp, 0..accounts, &descriptor)?;
     let addresses = precompute_addresses(&descriptors, max, network)?;
 
     let desc_type = addresses.get(&address);
@@ -70,27 +73,49 @@ pub(crate) fn precompute_addresses(
     network: Network,
 ) -> Result<HashMap<Address, DescriptorType>, Error> {
     let mut dd = HashMap::new();
-    for i in 0..max {
-        for desc in descriptors {
-            let definite_desc = desc.at_derivation_index(i)?;
-            let derived_address = definite_desc.address(network)?;
-            let desc_type = definite_desc.desc_type();
+    for desc in descriptors {
+        let desc_type = desc.desc_type();
+        for (_, derived_address) in derive_addresses(desc, 0..max, network)? {
             dd.insert(derived_address, desc_type);
         }
     }
     Ok(dd)
 }
 
+/// The addresses of a single path descriptor in the range of derivation indexes, only the one at
+/// index 0 if the descriptor has no wildcard
+pub(crate) fn derive_addresses(
+    desc: &Descriptor<DescriptorPublicKey>,
+    range: Range<u32>,
+    network: Network,
+) -> Result<Vec<(u32, Address)>, Error> {
+    let range = if desc.has_wildcard() { range } else { 0..1 };
+    range
+        .map(|i| Ok((i, desc.at_derivation_index(i)?.address(network)?)))
+        .collect()
+}
+
 pub(crate) fn compute_finite_descriptors(
     seed: &Seed,
     network: Network,
     secp: &Secp256k1<All>,
+    accounts: Range<u32>,
+    custom: &[String],
 ) -> Result<Vec<Descriptor<DescriptorPublicKey>>, Error> {
-    let descriptors = compute_descriptors(seed, network, &secp);
     let mut dd = vec![];
-    for d in descriptors {
-        for definite_desc in d.into_single_descriptors()? {
-            dd.push(definite_desc);
+    for account in accounts {
+        let descriptors = if custom.is_empty() {
+            compute_descriptors(seed, network, secp, account)
+        } else {
+            custom
+                .iter()
+                .map(|d| expand_descriptor(seed, network, secp, account, d))
+                .collect::<Result<_, _>>()?
+        };
+        for d in descriptors {
+            for definite_desc in d.into_single_descriptors()? {
+                dd.push(definite_desc);
+            }
         }
     }
     Ok(dd)
@@ -104,6 +129,7 @@ mod test {
     use crate::Seed;
 
     use super::Params;
+    use clap::Parser;
 
     const CODEX_32: &str = include_str!("../../wallet/CODEX_32");
 
@@ -118,11 +144,86 @@ mod test {
             &seed,
             Params {
                 address,
-                max: 10,
-                network: Network::Testnet,
+                wallet: crate::wallet::WalletParams {
+                    network: Network::Testnet,
+                    max: 10,
+                    accounts: 1,
+                    descriptor: vec![],
+                },
             },
         )
         .unwrap();
         assert!(result.spendable);
+
+        // first taproot address of account 2
+        let address =
+            Address::from_str("tb1p8vpsyf2qftzj424ft0p9za3tmfz5v954gf0exx300d705v4g0qeqwlvpgu")
+                .unwrap();
+        let params = |accounts| Params {
+            address: address.clone(),
+            wallet: crate::wallet::WalletParams {
+                network: Network::Testnet,
+                max: 10,
+                accounts,
+                descriptor: vec![],
+            },
+        };
+        assert!(!super::main(&seed, params(2)).unwrap().spendable);
+        assert!(super::main(&seed, params(3)).unwrap().spendable);
+    }
+
+    #[test]
+    fn test_spendable_descriptor() {
+        let seed: Seed = CODEX_32.parse().expect("test");
+        let heir = "[73c5da0a/48'/1'/0'/2']tpubDFH9dgzveyD8zTbPUFuLrGmCydNvxehyNdUXKJAQN8x4aZ4j6UZqGfnqFrD4NqyaTVGKbvEW54tsvPTK2UoSbCC1PJY8iCNiwTL3RWZEheQ/<0;1>/*";
+        let desc = format!("wsh(or_d(pk(@me),and_v(v:pk({heir}),older(52560))))");
+        let params = |address: &str, descriptor: Vec<String>| Params {
+            address: Address::from_str(address).expect("test"),
+            wallet: crate::wallet::WalletParams {
+                network: Network::Testnet,
+                max: 10,
+                accounts: 1,
+                descriptor,
+            },
+        };
+        let address = "tb1qetatrszc9vhls6dhlt7kclqthse7srmwgung0rj5668xvlkc746q5t8utg";
+        assert!(
+            super::main(&seed, params(address, vec![desc.clone()]))
+                .expect("test")
+                .spendable
+        );
+        assert!(
+            !super::main(&seed, params(address, vec![]))
+                .expect("test")
+                .spendable
+        );
+
+        // standard addresses are not scanned when descriptors are given
+        let address = "tb1pccadr74cd29xf5y0eax2dwnfvjeqwa65c9h09f7cw6c2h6c7rjysrh8wn0";
+        assert!(
+            !super::main(&seed, params(address, vec![desc]))
+                .expect("test")
+                .spendable
+        );
+    }
+
+    #[test]
+    fn test_accounts_parser() {
+        let parse = |accounts: &str| {
+            Params::try_parse_from([
+                "spendable",
+                "--address",
+                "tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c",
+                "--network",
+                "testnet",
+                "--accounts",
+                accounts,
+            ])
+        };
+        assert!(parse("0").is_err());
+        assert_eq!(parse("1").expect("test").wallet.accounts, 1);
+        assert_eq!(parse("1000").expect("test").wallet.accounts, 1000);
+        assert!(parse("1001").is_err());
+        assert!(parse("2147483648").is_err());
     }
 }
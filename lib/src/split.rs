@@ -0,0 +1,100 @@
+use crate::{error::Error, seed::Seed};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Takes a seed (bip39 or bip93) from standard input and split it in codex32 (bip93) shares,
+/// any `threshold` of them fed to standard input of the other commands recover the seed.
+///
+/// Unlike bip93, which generates the first `threshold - 1` shares randomly, they are derived
+/// from the seed with HMAC-SHA512: the same seed, `id` and `threshold` always give the same
+/// shares, and no source of randomness is needed on the offline machine. The bip39 passphrase
+/// is not part of the shares.
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// Number of shares needed to recover the seed, from 2 to 9
+    #[clap(short, long)]
+    pub threshold: usize,
+
+    /// Number of shares generated, from threshold to 31
+    #[clap(short, long)]
+    pub shares: usize,
+
+    /// Identifier of 4 bech32 characters, common to all the shares
+    #[clap(short, long)]
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// Number of shares needed to recover the seed
+    pub threshold: usize,
+
+    /// The codex32 shares
+    pub shares: Vec<String>,
+
+    /// Present if the seed was given with a passphrase, which is not part of the shares
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+pub fn main(seed: &Seed, params: Params) -> Result<Output, Error> {
+    let Params {
+        threshold,
+        shares,
+        id,
+    } = params;
+
+    let shares = seed.split(&id, threshold, shares)?;
+
+    let warning = (!seed.passphrase().is_empty()).then(|| {
+        "the passphrase is not part of the shares, it's needed along with them to recover the wallet"
+            .to_string()
+    });
+
+    Ok(Output {
+        threshold,
+        shares: shares.iter().map(ToString::to_string).collect(),
+        warning,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Seed;
+
+    use super::Params;
+
+    const MNEMONIC: &str = include_str!("../../wallet/MNEMONIC");
+
+    #[test]
+    fn test_split() {
+        let seed: Seed = MNEMONIC.parse().expect("test");
+        let params = Params {
+            threshold: 3,
+            shares: 5,
+            id: "fund".to_string(),
+        };
+        let result = super::main(&seed, params).expect("test");
+        assert_eq!(result.shares.len(), 5);
+
+        let lines = vec![
+            result.shares[4].clone(),
+            result.shares[0].clone(),
+            result.shares[2].clone(),
+        ];
+        let recovered = Seed::from_lines(&lines).expect("test");
+        assert_eq!(recovered.mnemonic(), seed.mnemonic());
+        assert!(result.warning.is_none());
+
+        let seed = seed.with_passphrase("TREZOR");
+        let params = Params {
+            threshold: 3,
+            shares: 5,
+            id: "fund".to_string(),
+        };
+        let with_passphrase = super::main(&seed, params).expect("test");
+        assert_eq!(with_passphrase.shares, result.shares);
+        assert!(with_passphrase.warning.is_some());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::sign;
+use crate::Error;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::Txid;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Verify the audit log written by `sign --audit-log`, every entry must contain the hash of the
+/// previous one, so that removed, reordered or modified entries are detected
+#[derive(Parser, Debug)]
+#[command(author, version)]
+pub struct Params {
+    /// The audit log file
+    pub log: PathBuf,
+
+    /// Hash of the last entry recorded elsewhere, like in a previous run of this command, detects
+    /// the truncation of the last entries
+    #[clap(long)]
+    pub head: Option<sha256::Hash>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+    /// Number of entries in the log
+    pub entries: u64,
+
+    /// Hash of the last entry, to be recorded elsewhere and passed with `--head` in future checks
+    pub head: sha256::Hash,
+}
+
+/// An entry of the audit log, one json line for every PSBT processed by `sign`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Position of the entry in the log, starting from 0
+    pub index: u64,
+
+    /// Unix timestamp of the machine clock when the entry was written
+    pub timestamp: u64,
+
+    /// Transaction id of the unsigned transaction
+    pub txid: Txid,
+
+    /// Transaction id of the final transaction, missing if some inputs are not finalized, like
+    /// with `--sign-only` or in a multisig waiting for other signatures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_txid: Option<Txid>,
+
+    /// Inputs as `amount:outpoint`, followed by ` mine` if ours
+    pub inputs: Vec<String>,
+
+    /// Outputs as `amount:address`, followed by ` mine` if ours
+    pub outputs: Vec<String>,
+
+    pub fee: u64,
+
+    pub bal: i64,
+
+    pub signatures_added: usize,
+
+    /// Inputs we signed as `index:sighash`, like `0:SIGHASH_ALL`
+    pub signed_inputs: Vec<String>,
+
+    /// Hash of the json line of the previous entry, all zeros for the first entry
+    pub prev_hash: sha256::Hash,
+}
+
+pub fn main(params: Params) -> Result<Output, Error> {
+    let content = std::fs::read_to_string(&params.log)?;
+    let (entries, head) = verify(&content)?;
+    if let Some(expected) = params.head {
+        if !content.lines().any(|l| hash_line(l) == expected) {
+            return Err(Error::String(format!(
+                "head {expected} not found, the log has been truncated or modified"
+            )));
+        }
+    }
+    Ok(Output { entries, head })
+}
+
+/// Returns the number of entries and the hash of the last one
+fn verify(content: &str) -> Result<(u64, sha256::Hash), Error> {
+    if !content.is_empty() && !content.ends_with('\n') {
+        return Err(Error::Other("the last entry is truncated"));
+    }
+    let mut head = sha256::Hash::all_zeros();
+    let mut entries = 0;
+    for (index, line) in content.lines().enumerate() {
+        let entry: LogEntry = serde_json::from_str(line)
+            .map_err(|e| Error::String(format!("entry #{index} is invalid: {e}")))?;
+        if entry.index != index as u64 {
+            return Err(Error::String(format!(
+                "entry #{index} has index {}, entries have been removed",
+                entry.index
+            )));
+        }
+        if entry.prev_hash != head {
+            return Err(Error::String(format!(
+                "entry #{index} doesn't match the hash of the previous entry"
+            )));
+        }
+        head = hash_line(line);
+        entries += 1;
+    }
+    Ok((entries, head))
+}
+
+fn hash_line(line: &str) -> sha256::Hash {
+    sha256::Hash::hash(line.as_bytes())
+}
+
+/// Append an entry for the given `sign` output at the end of the log, chained to the last entry
+pub(crate) fn append(log: &Path, output: &sign::Output) -> Result<(), Error> {
+    let content = match std::fs::read_to_string(log) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // the chain is verified so that we don't extend a tampered log
+    let (index, prev_hash) = verify(&content)?;
+
+    let psbt = output.psbt();
+    let entry = LogEntry {
+        index,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        txid: psbt.unsigned_tx.compute_txid(),
+        final_txid: output.txid,
+        inputs: output
+            .inputs
+            .iter()
+            .map(|i| format!("{}:{}{}", i.amount, i.outpoint, i.ownership.suffix()))
+            .collect(),
+        outputs: output
+            .outputs
+            .iter()
+            .map(|o| format!("{}:{}{}", o.amount, o.destination(), o.ownership.suffix()))
+            .collect(),
+        fee: output.fee,
+        bal: output.bal,
+        signatures_added: output.signatures_added,
+        signed_inputs: output
+            .signed_inputs
+            .iter()
+            .map(|i| format!("{i}:{}", output.inputs[*i].sighash))
+            .collect(),
+        prev_hash,
+    };
+    let line = serde_json::to_string(&entry).expect("doesn't contain non-string key");
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)?;
+    writeln!(file, "{line}")?;
+    // removable media could be unplugged right after
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify, LogEntry};
+
+    const ENTRY: &str = r#"{"index":0,"timestamp":1700000000,"txid":"a56fb5e42d0ddfa9d817947e1986d8381a4b0746685c27862c34c4dc88f55ca8","inputs":["5000000000:77f5c1516ab9fb6dfd1fa59f997a773dcc423b4165ae9269fe498f7ccdf41754:0 mine"],"outputs":["100000:tb1q0nqelwukrwcqlrjkxpr5ug7083ycfwp0228y3c"],"fee":1430,"bal":-101430,"signatures_added":1,"signed_inputs":["0:SIGHASH_ALL"],"prev_hash":"0000000000000000000000000000000000000000000000000000000000000000"}"#;
+
+    #[test]
+    fn test_verify() {
+        assert_eq!(verify("").expect("test").0, 0);
+
+        let first = format!("{ENTRY}\n");
+        let (entries, head) = verify(&first).expect("test");
+        assert_eq!(entries, 1);
+
+        let mut entry: LogEntry = serde_json::from_str(ENTRY).expect("test");
+        entry.index = 1;
+        entry.prev_hash = head;
+        let second = serde_json::to_string(&entry).expect("test");
+        let log = format!("{first}{second}\n");
+        assert_eq!(verify(&log).expect("test").0, 2);
+
+        // truncated, removed, modified, reordered
+        assert!(verify(&log[..log.len() - 2]).is_err());
+        assert!(verify(&format!("{second}\n")).is_err());
+        assert!(verify(&log.replace("-101430", "-1430")).is_err());
+        assert!(verify(&format!("{second}\n{first}")).is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::import::accounts_parser;
+use bitcoin::Network;
+use clap::Args;
+
+/// Options selecting the descriptors of the seed, shared by the commands checking if inputs,
+/// outputs, addresses or unspent outputs are ours
+#[derive(Args, Debug, Clone)]
+pub struct WalletParams {
+    /// Bitcoin Network. bitcoin,testnet,signet are possible values
+    #[clap(short, long, env)]
+    pub network: Network,
+
+    /// Addresses of every descriptor are generated up to this derivation index to recognize ours,
+    /// `sign` generates them only if some inputs of the PSBT don't have key origins
+    #[clap(short, long, default_value_t = 1000)]
+    pub max: u32,
+
+    /// Number of accounts scanned, starting from account 0, at most 1000
+    #[clap(long, default_value_t = 1, value_parser = accounts_parser())]
+    pub accounts: u32,
+
+    /// Descriptors used instead of the standard ones, `@me` is replaced with the key of the seed
+    /// `[fingerprint/48'/coin'/account'/2']xpub/<0;1>/*` for every scanned account, with `1'`
+    /// instead of `2'` in `sh(wsh())`. `@me` is refused in other descriptor types
+    #[clap(short, long)]
+    pub descriptor: Vec<String>,
+}
@@ -1,4 +1,4 @@
-use bitcoin::{Address, Amount, Network, Txid};
+use bitcoin::{Address, Amount, Txid};
 use bitcoind::{
     bitcoincore_rpc::{
         json::{AddressType, CreateRawTransactionInput, WalletCreateFundedPsbtOptions},
@@ -7,8 +7,11 @@ use bitcoind::{
     BitcoinD,
 };
 use firma2_lib::{
+    clap::Parser,
     import::{self},
-    sign, spendable, Seed,
+    sign, spendable,
+    wallet::WalletParams,
+    Seed,
 };
 use serde_json::Value;
 use std::collections::HashMap;
@@ -187,23 +190,20 @@ impl TestContext {
             .list_unspent(None, None, None, None, None)
             .unwrap();
         let inputs = create_raw_inputs(&unspents);
-        assert!(inputs.len() > 0, "no inputs");
+        assert!(!inputs.is_empty(), "no inputs");
 
         let psbt_result = self
             .wallet
             .wallet_create_funded_psbt(&inputs, &outputs, locktime, options, Some(true))
-            .expect(&format!(
-                "fail wallet_create_funded_psbt for {:?}",
-                self.kind
-            ));
+            .unwrap_or_else(|e| panic!("fail wallet_create_funded_psbt for {:?}: {e}", self.kind));
 
         let output = sign_psbt(&self.seed, &psbt_result.psbt);
         println!("{:?}", output);
-        let bal = output.bal.trim().parse::<i64>().unwrap();
+        let bal = output.bal;
         println!("{bal} {expected_balance}");
 
         assert!((bal.abs() - expected_balance.abs()).abs() < 3_000);
-        let tx = output.tx();
+        let tx = output.tx().expect("all inputs are finalized");
 
         // Validate and send
         let result = self.wallet.test_mempool_accept(&[&tx]).expect("test");
@@ -266,7 +266,7 @@ fn setup(kind: AddressType) -> TestContext {
 
     let seed: Seed = CODEX_32.parse().expect("test");
 
-    let desc = import::core_import_json(&seed, bitcoin::Network::Regtest).expect("test");
+    let desc = import::core_import_json(&seed, bitcoin::Network::Regtest, 0).expect("test");
     let desc_value = serde_json::to_value(desc).unwrap();
     let result = import_descriptors(&wallet, desc_value);
 
@@ -336,18 +336,22 @@ fn fund_wallet(
         .expect("test");
 
     let spendable = spendable::main(
-        &seed,
+        seed,
         spendable::Params {
             address: address.clone(),
-            network: bitcoin::Network::Regtest,
-            max: 1000,
+            wallet: WalletParams {
+                network: bitcoin::Network::Regtest,
+                max: 1000,
+                accounts: 1,
+                descriptor: vec![],
+            },
         },
     )
     .unwrap();
     let address = address.assume_checked();
 
     let _txid = send_to_address(&node.client, &address);
-    let _ = generate_to_own_address(&node, 1, kind);
+    let _ = generate_to_own_address(node, 1, kind);
 
     spendable
 }
@@ -370,15 +374,9 @@ fn sign_psbt(seed: &Seed, psbt_result: &str) -> sign::Output {
     f.as_file_mut()
         .write_all(psbt_result.as_bytes())
         .expect("Unable to write data");
-
-    let params = sign::Params {
-        psbts: vec![f.path().to_path_buf()],
-        network: Network::Regtest,
-        max: 20,
-    };
-    let signed = sign::main(seed, params).expect("test").remove(0);
-
-    signed
+    let path = f.path().to_str().expect("utf8 path");
+    let params = sign::Params::parse_from(["sign", "--network", "regtest", "--max", "20", path]);
+    sign::main(seed, params).expect("test").remove(0)
 }
 
 fn other_kind(kind: AddressType) -> AddressType {